@@ -22,10 +22,21 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
 
         match current.tok_type {
             // Variable def or function def
-            TokenType::Void | TokenType::Int | TokenType::Uint | TokenType::Float | TokenType::String | TokenType::Char => {
+            TokenType::Void
+            | TokenType::Int
+            | TokenType::Uint
+            | TokenType::Float
+            | TokenType::String
+            | TokenType::Character
+            | TokenType::Boolean
+            | TokenType::Const
+            | TokenType::Identifier
+                if current.tok_type != TokenType::Identifier || is_user_type_decl(&buf) =>
+            {
                 // Making the type
                 let var_type = make_type(&mut buf);
                 let ident = buf_consume!(buf, (TokenType::Identifier), src, "Expected identifier after type");
+                let mut var_type = make_arr_type(&mut buf, src, var_type);
                 let op = buf_consume!(
                     buf,
                     (TokenType::Assign, TokenType::OpenParen, TokenType::Semicolon),
@@ -53,6 +64,11 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
 
                         debug_sym_str += ";";
 
+                        // `int32 xs[] = {1, 2, 3};` takes its length from the initializer
+                        if let (Type::Arr(elem_type, None), Expr::Array(items)) = (&var_type, &expr) {
+                            var_type = Type::Arr(elem_type.clone(), Some(items.len() as u64))
+                        }
+
                         prog.statements.push((
                             DebugSym::new(debug_sym_str, lineno),
                             Node::VarDefine {
@@ -65,6 +81,10 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
 
                     // Variable declaration
                     TokenType::Semicolon => {
+                        if let Type::Const(_) = var_type {
+                            print_error("Constant must be initialized", src, ident.start, ident.end, ident.lineno);
+                            exit(2)
+                        }
                         debug_sym_str += ";";
                         prog.statements.push((
                             DebugSym::new(debug_sym_str, lineno),
@@ -88,6 +108,7 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
                         while buf.in_bounds() && buf.current("").tok_type != TokenType::CloseParen {
                             let arg_type = make_type(&mut buf);
                             let arg_ident = buf_consume!(buf, (TokenType::Identifier), src, "Expected identifier after type");
+                            let arg_type = make_arr_type(&mut buf, src, arg_type);
                             args.push((arg_type.clone(), arg_ident.val.clone()));
 
                             if !buf.in_bounds() {
@@ -101,7 +122,7 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
                             if curr.tok_type == TokenType::CloseParen {
                                 break;
                             }
-                            if curr.tok_type != TokenType::Colon {
+                            if curr.tok_type != TokenType::Comma {
                                 print_error("Expected ')' or ',' after identifier", src, curr.start, curr.end, curr.lineno);
                                 exit(2)
                             }
@@ -125,50 +146,209 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
                 }
             }
 
-            TokenType::Identifier => {
-                let ident = current.val;
-                buf.advance();
-                let op = buf_consume!(buf, (TokenType::Assign, TokenType::OpenParen), src, "Expected '=' or '(' after identifier");
+            TokenType::Identifier | TokenType::Mult | TokenType::OpenParen | TokenType::Inc | TokenType::Dec => {
+                // Function call
+                if current.tok_type == TokenType::Identifier && buf.peek().is_some_and(|tok| tok.tok_type == TokenType::OpenParen) {
+                    let ident = current.val;
+                    buf.advance();
+                    buf.advance();
 
-                debug_sym_str += ident.as_str();
+                    debug_sym_str += ident.as_str();
 
-                match op.tok_type {
-                    TokenType::Assign => {
-                        debug_sym_str += " = ";
+                    let args = args_parser(&mut buf, &mut debug_sym_str, src);
+                    buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after function call");
 
-                        let expr = expr_parser(&mut buf, &mut debug_sym_str, src);
-                        buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after variable assignment");
+                    debug_sym_str += ";";
 
+                    prog.statements
+                        .push((DebugSym::new(debug_sym_str, lineno), Node::FuncCall { name: ident, args }))
+                } else {
+                    let lhs = expr_parser(&mut buf, &mut debug_sym_str, src);
+
+                    // `i++;` on its own is the same as `i += 1;`
+                    if let Expr::IncDec { expr, op, .. } = lhs {
+                        buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after increment or decrement");
                         debug_sym_str += ";";
+                        prog.statements.push((
+                            DebugSym::new(debug_sym_str, lineno),
+                            Node::CompoundAssign {
+                                lhs: *expr,
+                                op,
+                                expr: Expr::Number(1),
+                            },
+                        ));
+                        continue;
+                    }
 
-                        prog.statements
-                            .push((DebugSym::new(debug_sym_str, lineno), Node::VarAssign { ident, expr }))
+                    if !is_lvalue(&lhs) {
+                        print_error("Expression cannot be assigned to", src, current.start, current.end, lineno);
+                        exit(2)
                     }
+                    let op = buf_consume!(
+                        buf,
+                        (
+                            TokenType::Assign,
+                            TokenType::AddAssign,
+                            TokenType::SubAssign,
+                            TokenType::MultAssign,
+                            TokenType::DivAssign,
+                            TokenType::ModAssign,
+                            TokenType::AndAssign,
+                            TokenType::OrAssign,
+                            TokenType::XorAssign,
+                            TokenType::ShlAssign,
+                            TokenType::ShrAssign
+                        ),
+                        src,
+                        "Expected '=' or compound assignment after assignment target"
+                    );
 
-                    TokenType::OpenParen => {
-                        let args = args_parser(&mut buf, &mut debug_sym_str, src);
-                        buf.advance();
-                        buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after function call");
+                    debug_sym_str += format!(" {} ", op.val).as_str();
 
-                        debug_sym_str += ";";
+                    let expr = expr_parser(&mut buf, &mut debug_sym_str, src);
+                    buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after variable assignment");
+
+                    debug_sym_str += ";";
+
+                    let node = match op.tok_type {
+                        TokenType::Assign => Node::VarAssign { lhs, expr },
+                        _ => Node::CompoundAssign {
+                            lhs,
+                            op: compound_assign_op(&op),
+                            expr,
+                        },
+                    };
+                    prog.statements.push((DebugSym::new(debug_sym_str, lineno), node))
+                }
+            }
 
-                        prog.statements
-                            .push((DebugSym::new(debug_sym_str, lineno), Node::FuncCall { name: ident, args }))
+            TokenType::Struct => {
+                buf.advance();
+                let name = buf_consume!(buf, (TokenType::Identifier), src, "Expected struct name after 'struct'").val;
+                buf_consume!(buf, (TokenType::OpenBrace), src, "Expected '{' after struct name");
+
+                debug_sym_str += format!("struct {}", name).as_str();
+
+                let mut fields: Vec<(Type, String)> = vec![];
+                while buf.current("Expected '}' after struct fields").tok_type != TokenType::CloseBrace {
+                    let field_type = make_type(&mut buf);
+                    let field = buf_consume!(buf, (TokenType::Identifier), src, "Expected field name after type");
+                    let field_type = make_arr_type(&mut buf, src, field_type);
+                    if fields.iter().any(|(_, name)| *name == field.val) {
+                        print_error("Duplicate struct field", src, field.start, field.end, field.lineno);
+                        exit(2)
                     }
+                    buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after struct field");
+                    fields.push((field_type, field.val))
+                }
+                buf.advance();
 
-                    _ => unreachable!(),
+                prog.statements
+                    .push((DebugSym::new(debug_sym_str, lineno), Node::Struct { name, fields }))
+            }
+
+            TokenType::Enum => {
+                buf.advance();
+                let name = buf_consume!(buf, (TokenType::Identifier), src, "Expected enum name after 'enum'").val;
+                buf_consume!(buf, (TokenType::OpenBrace), src, "Expected '{' after enum name");
+
+                debug_sym_str += format!("enum {}", name).as_str();
+
+                let mut variants: Vec<(String, Option<Expr>)> = vec![];
+                while buf.current("Expected '}' after enum variants").tok_type != TokenType::CloseBrace {
+                    let variant = buf_consume!(buf, (TokenType::Identifier), src, "Expected variant name");
+                    if variants.iter().any(|(name, _)| *name == variant.val) {
+                        print_error("Duplicate enum variant", src, variant.start, variant.end, variant.lineno);
+                        exit(2)
+                    }
+                    let mut value = None;
+                    if buf.current("Expected ',' or '}' after enum variant").tok_type == TokenType::Assign {
+                        buf.advance();
+                        value = Some(expr_parser(&mut buf, &mut String::new(), src))
+                    }
+                    variants.push((variant.val, value));
+                    if buf.current("Expected ',' or '}' after enum variant").tok_type == TokenType::CloseBrace {
+                        break;
+                    }
+                    buf_consume!(buf, (TokenType::Comma), src, "Expected ',' or '}' after enum variant");
                 }
+                buf.advance();
+
+                prog.statements
+                    .push((DebugSym::new(debug_sym_str, lineno), Node::Enum { name, variants }))
             }
 
-            TokenType::If => {
+            TokenType::Union => {
                 buf.advance();
-                let expr = expr_parser(&mut buf, &mut debug_sym_str, src);
-                let body = sub_program(&mut buf, src, "if statement");
+                let name = buf_consume!(buf, (TokenType::Identifier), src, "Expected union name after 'union'").val;
+                buf_consume!(buf, (TokenType::OpenBrace), src, "Expected '{' after union name");
+
+                debug_sym_str += format!("union {}", name).as_str();
+
+                let mut variants: Vec<(String, Vec<(Type, String)>)> = vec![];
+                while buf.current("Expected '}' after union variants").tok_type != TokenType::CloseBrace {
+                    let variant = buf_consume!(buf, (TokenType::Identifier), src, "Expected variant name");
+                    if variants.iter().any(|(name, _)| *name == variant.val) {
+                        print_error("Duplicate union variant", src, variant.start, variant.end, variant.lineno);
+                        exit(2)
+                    }
+                    let mut fields: Vec<(Type, String)> = vec![];
+                    if buf.current("Expected ',' or '}' after union variant").tok_type == TokenType::OpenParen {
+                        buf.advance();
+                        while buf.current("Expected ')' after variant fields").tok_type != TokenType::CloseParen {
+                            let field_type = make_type(&mut buf);
+                            let field = buf_consume!(buf, (TokenType::Identifier), src, "Expected field name after type");
+                            let field_type = make_arr_type(&mut buf, src, field_type);
+                            if fields.iter().any(|(_, name)| *name == field.val) {
+                                print_error("Duplicate variant field", src, field.start, field.end, field.lineno);
+                                exit(2)
+                            }
+                            fields.push((field_type, field.val));
+                            if buf.current("Expected ')' after variant fields").tok_type == TokenType::CloseParen {
+                                break;
+                            }
+                            buf_consume!(buf, (TokenType::Comma), src, "Expected ',' or ')' after variant field");
+                        }
+                        buf.advance()
+                    }
+                    variants.push((variant.val, fields));
+                    if buf.current("Expected ',' or '}' after union variant").tok_type == TokenType::CloseBrace {
+                        break;
+                    }
+                    buf_consume!(buf, (TokenType::Comma), src, "Expected ',' or '}' after union variant");
+                }
+                buf.advance();
+
                 prog.statements
-                    .push((DebugSym::new(debug_sym_str, lineno), Node::If { cond: expr, body }))
+                    .push((DebugSym::new(debug_sym_str, lineno), Node::Union { name, variants }))
+            }
+
+            TokenType::Typedef | TokenType::Type => {
+                buf.advance();
+                let (name, typ) = if current.tok_type == TokenType::Typedef {
+                    let typ = make_type(&mut buf);
+                    let name = buf_consume!(buf, (TokenType::Identifier), src, "Expected alias name after type").val;
+                    (name, make_arr_type(&mut buf, src, typ))
+                } else {
+                    let name = buf_consume!(buf, (TokenType::Identifier), src, "Expected alias name after 'type'").val;
+                    buf_consume!(buf, (TokenType::Assign), src, "Expected '=' after alias name");
+                    let typ = make_type(&mut buf);
+                    (name, make_arr_type(&mut buf, src, typ))
+                };
+                buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after type alias");
+
+                debug_sym_str += format!("type {} = {};", name, typ).as_str();
+                prog.statements.push((DebugSym::new(debug_sym_str, lineno), Node::Typedef { name, typ }))
             }
 
+            TokenType::Match => prog.statements.push(match_statement(&mut buf, src)),
+
+            TokenType::Switch => prog.statements.push(switch_statement(&mut buf, src)),
+
+            TokenType::If => prog.statements.push(if_statement(&mut buf, src)),
+
             TokenType::While => {
+                debug_sym_str += "while ";
                 buf.advance();
                 let expr = expr_parser(&mut buf, &mut debug_sym_str, src);
                 let body = sub_program(&mut buf, src, "while statement");
@@ -176,6 +356,37 @@ pub fn make_ast(src: &String, toks: &[Token]) -> Program {
                     .push((DebugSym::new(debug_sym_str, lineno), Node::While { cond: expr, body }))
             }
 
+            TokenType::For => prog.statements.push(for_statement(&mut buf, src)),
+
+            TokenType::Do => {
+                debug_sym_str += "do while ";
+                buf.advance();
+                let body = sub_program(&mut buf, src, "do statement");
+                buf_consume!(buf, (TokenType::While), src, "Expected 'while' after do statement body");
+                let expr = expr_parser(&mut buf, &mut debug_sym_str, src);
+                buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after do while condition");
+                prog.statements
+                    .push((DebugSym::new(debug_sym_str, lineno), Node::DoWhile { body, cond: expr }))
+            }
+
+            TokenType::OpenBrace => {
+                let body = sub_program(&mut buf, src, "block");
+                prog.statements.push((DebugSym::new(String::from("{ ... }"), lineno), Node::Block(body)))
+            }
+
+            TokenType::Break | TokenType::Continue => {
+                buf.advance();
+                buf_consume!(buf, (TokenType::Semicolon), src, format!("Expected ';' after '{}'", current.val).as_str());
+                debug_sym_str += format!("{};", current.val).as_str();
+
+                let node = if current.tok_type == TokenType::Break {
+                    Node::Break
+                } else {
+                    Node::Continue
+                };
+                prog.statements.push((DebugSym::new(debug_sym_str, lineno), node))
+            }
+
             TokenType::Import => {
                 let mut lib = Vec::new();
 
@@ -266,6 +477,10 @@ impl TokenBuffer {
     pub fn advance(&mut self) {
         self.pos += 1
     }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.toks.get(self.pos + 1)
+    }
     #[allow(dead_code)]
     pub fn next(&mut self, err: &str) -> &Token {
         self.advance();
@@ -295,49 +510,327 @@ macro_rules! buf_consume {
 }
 
 fn make_type(buf: &mut TokenBuffer) -> Type {
-    let mut var_type = Type::Named(buf.current("").val.clone());
+    let is_const = buf.current("").tok_type == TokenType::Const;
+    if is_const {
+        buf.advance()
+    }
+    let mut var_type = Type::Named(buf.current("Expected type after 'const'").val.clone());
+    if is_const {
+        var_type = Type::Const(Box::new(var_type))
+    }
+    buf.advance();
     while buf.in_bounds() {
-        let curr = buf.current("");
-        if curr.tok_type == TokenType::Identifier {
-            return var_type;
-        }
-        if curr.tok_type == TokenType::Mult {
-            var_type = Type::Ptr(Box::new(var_type))
+        match buf.current("").tok_type {
+            TokenType::Mult => var_type = Type::Ptr(Box::new(var_type)),
+            // `int32* const` makes the pointer itself constant
+            TokenType::Const => var_type = Type::Const(Box::new(var_type)),
+            _ => return var_type,
         }
         buf.advance()
     }
     var_type
 }
 
+// Array dimensions after an identifier, e.g. `int32 grid[4][8]` or `int32 buf[SIZE * 2]`
+fn make_arr_type(buf: &mut TokenBuffer, src: &String, typ: Type) -> Type {
+    let mut dims = vec![];
+    while buf.in_bounds() && buf.current("").tok_type == TokenType::OpenBracket {
+        let open = buf.current("").clone();
+        buf.advance();
+        if buf.current("Expected array length or ']'").tok_type == TokenType::CloseBracket {
+            // Only the outermost length can be left out
+            if !dims.is_empty() {
+                print_error("Expected array length", src, open.start, open.end, open.lineno);
+                exit(2)
+            }
+            dims.push(None)
+        } else {
+            let mut len_str = String::new();
+            let len = expr_parser(buf, &mut len_str, src);
+            dims.push(Some((len, len_str)))
+        }
+        buf_consume!(buf, (TokenType::CloseBracket), src, "Expected ']' after array length");
+    }
+
+    dims.into_iter().rev().fold(typ, |typ, len| match len {
+        None => Type::Arr(Box::new(typ), None),
+        Some((Expr::Number(len), _)) if len >= 0 => Type::Arr(Box::new(typ), Some(len as u64)),
+        // Anything else is left to the compiler, which knows the values of constants
+        Some((len, len_str)) => Type::ArrExpr(Box::new(typ), Box::new(len), len_str),
+    })
+}
+
+// Whether the parentheses after `sizeof` hold a type. A name followed by pointers or array lengths, e.g. `sizeof(Point*)`
+// or `sizeof(Point[4])`, is read as one, the compiler turns it back into an expression if the name is a variable.
+fn is_sizeof_type(buf: &TokenBuffer) -> bool {
+    let Some(first) = buf.peek() else {
+        return false;
+    };
+    if first.tok_type != TokenType::Identifier {
+        return is_datatype(first);
+    }
+
+    let mut pos = buf.pos + 2;
+    let tok_type = |pos: usize| buf.toks.get(pos).map(|tok| tok.tok_type.clone());
+    while matches!(tok_type(pos), Some(TokenType::Mult | TokenType::Const)) {
+        pos += 1
+    }
+    while tok_type(pos) == Some(TokenType::OpenBracket) {
+        let mut depth = 0;
+        loop {
+            match tok_type(pos) {
+                Some(TokenType::OpenBracket) => depth += 1,
+                Some(TokenType::CloseBracket) => depth -= 1,
+                None => return false,
+                _ => {}
+            }
+            pos += 1;
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+    pos > buf.pos + 2 && tok_type(pos) == Some(TokenType::CloseParen)
+}
+
+// Whether an identifier at the start of a statement is a user defined type, e.g. `Point p;`
+fn is_user_type_decl(buf: &TokenBuffer) -> bool {
+    buf.peek()
+        .is_some_and(|tok| matches!(tok.tok_type, TokenType::Identifier | TokenType::Mult | TokenType::Const))
+}
+
+fn compound_assign_op(tok: &Token) -> Operation {
+    match tok.tok_type {
+        TokenType::AddAssign => Operation::Add,
+        TokenType::SubAssign => Operation::Sub,
+        TokenType::MultAssign => Operation::Mult,
+        TokenType::DivAssign => Operation::Div,
+        TokenType::ModAssign => Operation::Mod,
+        TokenType::AndAssign => Operation::BitAnd,
+        TokenType::OrAssign => Operation::BitOr,
+        TokenType::XorAssign => Operation::BitXor,
+        TokenType::ShlAssign => Operation::Shl,
+        TokenType::ShrAssign => Operation::Shr,
+        _ => unreachable!(),
+    }
+}
+
+fn is_lvalue(expr: &Expr) -> bool {
+    matches!(expr, Expr::Ident(_) | Expr::Member { .. } | Expr::Index { .. } | Expr::Deref(_))
+}
+
 fn expr_parser(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
     fn factor(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        // Prefix operators apply to everything after them, including postfix operators
+        match buf.current("Expected expression").tok_type {
+            TokenType::Mult => {
+                buf.advance();
+                *debug_sym_str += "*";
+                return Expr::Deref(Box::new(factor(buf, debug_sym_str, src)));
+            }
+            TokenType::Ampersand => {
+                buf.advance();
+                *debug_sym_str += "&";
+                return Expr::AddrOf(Box::new(factor(buf, debug_sym_str, src)));
+            }
+            TokenType::Not => {
+                buf.advance();
+                *debug_sym_str += "!";
+                return Expr::Not(Box::new(factor(buf, debug_sym_str, src)));
+            }
+            TokenType::Minus => {
+                buf.advance();
+                *debug_sym_str += "-";
+                // Negative literals are plain numbers right away
+                return match factor(buf, debug_sym_str, src) {
+                    Expr::Number(num) => Expr::Number(num.wrapping_neg()),
+                    Expr::Float(val) => Expr::Float(-val),
+                    expr => Expr::Neg(Box::new(expr)),
+                };
+            }
+            // `sizeof(T)` takes a type when the parentheses start with one, `sizeof(Point)` is read as an expression
+            // and the compiler takes names that are no variable as types
+            TokenType::Sizeof => {
+                buf.advance();
+                *debug_sym_str += "sizeof ";
+                if buf.current("Expected expression after 'sizeof'").tok_type == TokenType::OpenParen && is_sizeof_type(buf) {
+                    buf.advance();
+                    let typ = make_type(buf);
+                    let typ = make_arr_type(buf, src, typ);
+                    buf_consume!(buf, (TokenType::CloseParen), src, "Expected ')' after type");
+                    *debug_sym_str += format!("({})", typ).as_str();
+                    return Expr::SizeOfType(typ);
+                }
+                return Expr::SizeOf(Box::new(factor(buf, debug_sym_str, src)));
+            }
+            TokenType::Alignof => {
+                let tok = buf.current("").clone();
+                buf.advance();
+                buf_consume!(buf, (TokenType::OpenParen), src, "Expected '(' after 'alignof'");
+                if !is_datatype(buf.current("Expected type after 'alignof'")) {
+                    print_error("Expected type after 'alignof'", src, tok.start, tok.end, tok.lineno);
+                    exit(2)
+                }
+                let typ = make_type(buf);
+                let typ = make_arr_type(buf, src, typ);
+                buf_consume!(buf, (TokenType::CloseParen), src, "Expected ')' after type");
+                *debug_sym_str += format!("alignof({})", typ).as_str();
+                return Expr::AlignOf(typ);
+            }
+            TokenType::Tilde => {
+                buf.advance();
+                *debug_sym_str += "~";
+                return Expr::BitNot(Box::new(factor(buf, debug_sym_str, src)));
+            }
+            TokenType::Inc | TokenType::Dec => {
+                let tok = buf.current("").clone();
+                buf.advance();
+                *debug_sym_str += tok.val.as_str();
+                let expr = factor(buf, debug_sym_str, src);
+                if !is_lvalue(&expr) {
+                    print_error("Expression cannot be incremented or decremented", src, tok.start, tok.end, tok.lineno);
+                    exit(2)
+                }
+                return Expr::IncDec {
+                    expr: Box::new(expr),
+                    op: if tok.tok_type == TokenType::Inc {
+                        Operation::Add
+                    } else {
+                        Operation::Sub
+                    },
+                    prefix: true,
+                };
+            }
+            _ => {}
+        }
+
+        let mut node = primary(buf, debug_sym_str, src);
+        while buf.in_bounds() {
+            match buf.current("").tok_type {
+                TokenType::Dot => {
+                    buf.advance();
+                    let field = buf_consume!(buf, (TokenType::Identifier), src, "Expected field name after '.'");
+                    *debug_sym_str += format!(".{}", field.val).as_str();
+                    node = Expr::Member {
+                        expr: Box::new(node),
+                        field: field.val,
+                    }
+                }
+
+                TokenType::Inc | TokenType::Dec => {
+                    let tok = buf.current("").clone();
+                    if !is_lvalue(&node) {
+                        print_error("Expression cannot be incremented or decremented", src, tok.start, tok.end, tok.lineno);
+                        exit(2)
+                    }
+                    buf.advance();
+                    *debug_sym_str += tok.val.as_str();
+                    node = Expr::IncDec {
+                        expr: Box::new(node),
+                        op: if tok.tok_type == TokenType::Inc {
+                            Operation::Add
+                        } else {
+                            Operation::Sub
+                        },
+                        prefix: false,
+                    }
+                }
+
+                TokenType::OpenBracket => {
+                    buf.advance();
+                    *debug_sym_str += "[";
+                    let index = ternary(buf, debug_sym_str, src);
+                    buf_consume!(buf, (TokenType::CloseBracket), src, "Missing closing ']'");
+                    *debug_sym_str += "]";
+                    node = Expr::Index {
+                        expr: Box::new(node),
+                        index: Box::new(index),
+                    }
+                }
+
+                _ => break,
+            }
+        }
+        node
+    }
+
+    fn primary(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
         let tok = buf_consume!(
             buf,
-            (TokenType::Num, TokenType::Identifier, TokenType::Str, TokenType::OpenParen),
+            (
+                TokenType::Num,
+                TokenType::Flt,
+                TokenType::Char,
+                TokenType::Bool,
+                TokenType::Identifier,
+                TokenType::Str,
+                TokenType::OpenParen,
+                TokenType::OpenBrace
+            ),
             src,
-            "Expected number or identifier or string or open paren"
+            "Expected number or identifier or string or character or boolean or open paren or array literal"
         );
-        *debug_sym_str += tok.val.as_str();
         match tok.tok_type {
-            TokenType::Num => Expr::Number(tok.val.parse::<i64>().unwrap()),
+            TokenType::Char => *debug_sym_str += format!("{:?}", tok.val.chars().next().unwrap()).as_str(),
+            _ => *debug_sym_str += tok.val.as_str(),
+        }
+        match tok.tok_type {
+            // Literals up to the largest uint64 keep their bits, even if they read as negative
+            TokenType::Num => match tok.val.parse::<u64>() {
+                Ok(num) => Expr::Number(num as i64),
+                Err(_) => {
+                    print_error("Integer literal is too large", src, tok.start, tok.end, tok.lineno);
+                    exit(2)
+                }
+            },
+            TokenType::Flt => Expr::Float(tok.val.parse::<f64>().unwrap()),
+            TokenType::Char => Expr::Char(tok.val.chars().next().unwrap()),
+            TokenType::Bool => Expr::Bool(tok.val == "true"),
             TokenType::Identifier => {
                 if buf.current("Expected operation or '(' or ';' after identifier").tok_type == TokenType::OpenParen {
                     buf.advance();
-                    *debug_sym_str += "(";
                     let args = args_parser(buf, debug_sym_str, src);
-                    *debug_sym_str += ")";
-                    buf.advance();
                     return Expr::FuncCall { name: tok.val, args };
                 }
+                if buf.current("").tok_type == TokenType::DoubleColon {
+                    buf.advance();
+                    let variant = buf_consume!(buf, (TokenType::Identifier), src, "Expected variant name after '::'").val;
+                    *debug_sym_str += format!("::{}", variant).as_str();
+                    if buf.in_bounds() && buf.current("").tok_type == TokenType::OpenParen {
+                        buf.advance();
+                        let args = args_parser(buf, debug_sym_str, src);
+                        return Expr::UnionValue {
+                            name: tok.val,
+                            variant,
+                            args,
+                        };
+                    }
+                    return Expr::EnumValue { name: tok.val, variant };
+                }
                 Expr::Ident(tok.val)
             }
             TokenType::Str => Expr::Str(tok.val),
             TokenType::OpenParen => {
-                let node = expr(buf, debug_sym_str, src);
+                let node = ternary(buf, debug_sym_str, src);
                 *debug_sym_str += ")";
                 buf_consume!(buf, (TokenType::CloseParen), src, "Missing closing ')'");
                 node
             }
+            TokenType::OpenBrace => {
+                let mut items = vec![];
+                while buf.current("Expected '}' after array literal").tok_type != TokenType::CloseBrace {
+                    items.push(ternary(buf, debug_sym_str, src));
+                    let tok = buf_consume!(buf, (TokenType::Comma, TokenType::CloseBrace), src, "Expected ',' or '}' after array element");
+                    if tok.tok_type == TokenType::CloseBrace {
+                        *debug_sym_str += "}";
+                        return Expr::Array(items);
+                    }
+                    *debug_sym_str += ", "
+                }
+                buf.advance();
+                *debug_sym_str += "}";
+                Expr::Array(items)
+            }
             _ => unreachable!(),
         }
     }
@@ -349,22 +842,44 @@ fn expr_parser(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String)
             TokenType::Mult => Operation::Mult,
             TokenType::Div => Operation::Div,
             TokenType::Mod => Operation::Mod,
+            TokenType::Ampersand => Operation::BitAnd,
+            TokenType::Pipe => Operation::BitOr,
+            TokenType::Caret => Operation::BitXor,
+            TokenType::Shl => Operation::Shl,
+            TokenType::Shr => Operation::Shr,
             _ => {
                 unreachable!()
             }
         }
     }
 
-    fn term(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+    // `x as T` binds tighter than any binary operator, but applies to prefix operators too
+    fn cast(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
         let mut node = factor(buf, debug_sym_str, src);
-        while buf.current("Expected operation").tok_type == TokenType::Mult || buf.current("").tok_type == TokenType::Div {
+        while buf.current("Expected operation").tok_type == TokenType::As {
+            let as_tok = buf.current("").clone();
+            buf.advance();
+            if !buf.in_bounds() || !is_datatype(buf.current("")) {
+                print_error("Expected type after 'as'", src, as_tok.start, as_tok.end, as_tok.lineno);
+                exit(2)
+            }
+            let typ = make_type(buf);
+            *debug_sym_str += format!(" as {}", typ).as_str();
+            node = Expr::Cast { expr: Box::new(node), typ };
+        }
+        node
+    }
+
+    fn term(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        let mut node = cast(buf, debug_sym_str, src);
+        while matches!(buf.current("Expected operation").tok_type, TokenType::Mult | TokenType::Div | TokenType::Mod) {
             let op = buf.current("").clone();
             *debug_sym_str += format!(" {} ", op.val).as_str();
             buf.advance();
             node = Expr::BiOp {
                 lhs: Box::new(node),
                 op: tok_to_op(&op),
-                rhs: Box::new(factor(buf, debug_sym_str, src)),
+                rhs: Box::new(cast(buf, debug_sym_str, src)),
             };
         }
         node
@@ -385,7 +900,117 @@ fn expr_parser(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String)
         node
     }
 
-    expr(buf, debug_sym_str, src)
+    fn tok_to_comp(tok: &Token) -> Option<Comparison> {
+        match tok.tok_type {
+            TokenType::EQ => Some(Comparison::EQ),
+            TokenType::NEQ => Some(Comparison::NEQ),
+            TokenType::LT => Some(Comparison::LT),
+            TokenType::LTE => Some(Comparison::LTE),
+            TokenType::GT => Some(Comparison::GT),
+            TokenType::GTE => Some(Comparison::GTE),
+            _ => None,
+        }
+    }
+
+    fn shift(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        let mut node = expr(buf, debug_sym_str, src);
+        while buf.current("Expected operation").tok_type == TokenType::Shl || buf.current("").tok_type == TokenType::Shr {
+            let op = buf.current("").clone();
+            *debug_sym_str += format!(" {} ", op.val).as_str();
+            buf.advance();
+            node = Expr::BiOp {
+                lhs: Box::new(node),
+                op: tok_to_op(&op),
+                rhs: Box::new(expr(buf, debug_sym_str, src)),
+            };
+        }
+        node
+    }
+
+    fn comparison(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        let mut node = shift(buf, debug_sym_str, src);
+        while let Some(comp) = tok_to_comp(buf.current("Expected operation")) {
+            let op = buf.current("").clone();
+            *debug_sym_str += format!(" {} ", op.val).as_str();
+            buf.advance();
+            node = Expr::Comp {
+                lhs: Box::new(node),
+                comp,
+                rhs: Box::new(shift(buf, debug_sym_str, src)),
+            };
+        }
+        node
+    }
+
+    // Bitwise operators bind looser than comparisons, like in C
+    fn bitwise(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String, level: usize) -> Expr {
+        const LEVELS: [TokenType; 3] = [TokenType::Pipe, TokenType::Caret, TokenType::Ampersand];
+        if level == LEVELS.len() {
+            return comparison(buf, debug_sym_str, src);
+        }
+
+        let mut node = bitwise(buf, debug_sym_str, src, level + 1);
+        while buf.current("Expected operation").tok_type == LEVELS[level] {
+            let op = buf.current("").clone();
+            *debug_sym_str += format!(" {} ", op.val).as_str();
+            buf.advance();
+            node = Expr::BiOp {
+                lhs: Box::new(node),
+                op: tok_to_op(&op),
+                rhs: Box::new(bitwise(buf, debug_sym_str, src, level + 1)),
+            };
+        }
+        node
+    }
+
+    fn logic_and(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        let mut node = bitwise(buf, debug_sym_str, src, 0);
+        while buf.current("Expected operation").tok_type == TokenType::And {
+            *debug_sym_str += " && ";
+            buf.advance();
+            node = Expr::Logic {
+                lhs: Box::new(node),
+                op: Logic::And,
+                rhs: Box::new(bitwise(buf, debug_sym_str, src, 0)),
+            };
+        }
+        node
+    }
+
+    fn logic_or(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        let mut node = logic_and(buf, debug_sym_str, src);
+        while buf.current("Expected operation").tok_type == TokenType::Or {
+            *debug_sym_str += " || ";
+            buf.advance();
+            node = Expr::Logic {
+                lhs: Box::new(node),
+                op: Logic::Or,
+                rhs: Box::new(logic_and(buf, debug_sym_str, src)),
+            };
+        }
+        node
+    }
+
+    // Binds looser than everything but assignment and groups to the right, so `a ? b : c ? d : e` chains
+    fn ternary(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Expr {
+        let cond = logic_or(buf, debug_sym_str, src);
+        if buf.current("Expected operation").tok_type != TokenType::Question {
+            return cond;
+        }
+        buf.advance();
+        *debug_sym_str += " ? ";
+        let then = ternary(buf, debug_sym_str, src);
+        buf_consume!(buf, (TokenType::Colon), src, "Expected ':' in conditional expression");
+        *debug_sym_str += " : ";
+        let otherwise = ternary(buf, debug_sym_str, src);
+        Expr::Ternary {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+        }
+    }
+
+    ternary(buf, debug_sym_str, src)
 }
 
 fn args_parser(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String) -> Vec<Expr> {
@@ -393,10 +1018,15 @@ fn args_parser(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String)
 
     *debug_sym_str += "(";
 
-    while buf.current("Expected expression for argument").tok_type != TokenType::CloseParen {
+    if buf.current("Expected expression for argument").tok_type == TokenType::CloseParen {
+        buf.advance();
+        *debug_sym_str += ")";
+        return args;
+    }
+    loop {
         let expr = expr_parser(buf, debug_sym_str, src);
         args.push(expr);
-        let tok = buf_consume!(buf, (TokenType::Comma, TokenType::CloseParen), src, "Expected ',' or '(' after argument expression");
+        let tok = buf_consume!(buf, (TokenType::Comma, TokenType::CloseParen), src, "Expected ',' or ')' after argument expression");
         if tok.tok_type == TokenType::CloseParen {
             break;
         }
@@ -408,6 +1038,208 @@ fn args_parser(buf: &mut TokenBuffer, debug_sym_str: &mut String, src: &String)
     args
 }
 
+fn if_statement(buf: &mut TokenBuffer, src: &String) -> (DebugSym, Node) {
+    let lineno = buf.current("").lineno;
+    let mut debug_sym_str = String::from("if ");
+
+    buf.advance();
+    let cond = expr_parser(buf, &mut debug_sym_str, src);
+    let body = sub_program(buf, src, "if statement");
+
+    let mut else_body = None;
+    if buf.in_bounds() && buf.current("").tok_type == TokenType::Else {
+        buf.advance();
+        if buf.current("Expected '{' or 'if' after 'else'").tok_type == TokenType::If {
+            let mut chain = Program::new();
+            chain.statements.push(if_statement(buf, src));
+            else_body = Some(chain)
+        } else {
+            else_body = Some(sub_program(buf, src, "else statement"))
+        }
+    }
+
+    (DebugSym::new(debug_sym_str, lineno), Node::If { cond, body, else_body })
+}
+
+fn match_statement(buf: &mut TokenBuffer, src: &String) -> (DebugSym, Node) {
+    let lineno = buf.current("").lineno;
+    let mut debug_sym_str = String::from("match ");
+
+    buf.advance();
+    let expr = expr_parser(buf, &mut debug_sym_str, src);
+    buf_consume!(buf, (TokenType::OpenBrace), src, "Expected '{' for match statement");
+
+    let mut arms = vec![];
+    while buf.current("Expected '}' for match statement").tok_type != TokenType::CloseBrace {
+        let variant = buf_consume!(buf, (TokenType::Identifier), src, "Expected variant name or '_'");
+        let mut arm_sym_str = variant.val.clone();
+        let pattern = if variant.val == "_" {
+            Pattern::Wildcard
+        } else if buf.current("Expected '=>' after pattern").tok_type == TokenType::OpenParen {
+            buf.advance();
+            let mut bindings = vec![];
+            while buf.current("Expected ')' after pattern").tok_type != TokenType::CloseParen {
+                bindings.push(buf_consume!(buf, (TokenType::Identifier), src, "Expected name to bind the field to").val);
+                if buf.current("Expected ')' after pattern").tok_type == TokenType::CloseParen {
+                    break;
+                }
+                buf_consume!(buf, (TokenType::Comma), src, "Expected ',' or ')' after binding");
+            }
+            buf.advance();
+            arm_sym_str += format!("({})", bindings.join(", ")).as_str();
+            Pattern::Variant {
+                name: variant.val,
+                bindings: Some(bindings),
+            }
+        } else {
+            Pattern::Variant {
+                name: variant.val,
+                bindings: None,
+            }
+        };
+        buf_consume!(buf, (TokenType::FatArrow), src, "Expected '=>' after pattern");
+        arm_sym_str += " =>";
+
+        // Either a block or a single statement
+        let body = if buf.current("Expected statement after '=>'").tok_type == TokenType::OpenBrace {
+            let body = sub_program(buf, src, "match arm");
+            if buf.current("Expected '}' for match statement").tok_type == TokenType::Comma {
+                buf.advance()
+            }
+            body
+        } else {
+            let mut body = vec![];
+            loop {
+                let curr = buf.current("Expected ';' after match arm").clone();
+                buf.advance();
+                let is_end = curr.tok_type == TokenType::Semicolon;
+                body.push(curr);
+                if is_end {
+                    break;
+                }
+            }
+            make_ast(src, &body)
+        };
+        arms.push((DebugSym::new(arm_sym_str, variant.lineno), pattern, body))
+    }
+    buf.advance();
+
+    (DebugSym::new(debug_sym_str, lineno), Node::Match { expr, arms })
+}
+
+fn switch_statement(buf: &mut TokenBuffer, src: &String) -> (DebugSym, Node) {
+    let lineno = buf.current("").lineno;
+    let mut debug_sym_str = String::from("switch ");
+
+    buf.advance();
+    let expr = expr_parser(buf, &mut debug_sym_str, src);
+    buf_consume!(buf, (TokenType::OpenBrace), src, "Expected '{' for switch statement");
+
+    let mut cases: Vec<(DebugSym, Option<Expr>, Program)> = vec![];
+    while buf.current("Expected '}' for switch statement").tok_type != TokenType::CloseBrace {
+        let label = buf_consume!(buf, (TokenType::Case, TokenType::Default), src, "Expected 'case' or 'default'");
+        let mut case_sym_str = format!("{} ", label.val);
+        let value = if label.tok_type == TokenType::Case {
+            Some(expr_parser(buf, &mut case_sym_str, src))
+        } else {
+            if cases.iter().any(|(_, value, _)| value.is_none()) {
+                print_error("Multiple default cases", src, label.start, label.end, label.lineno);
+                exit(2)
+            }
+            None
+        };
+        buf_consume!(buf, (TokenType::Colon), src, "Expected ':' after case");
+        case_sym_str = case_sym_str.trim_end().to_owned() + ":";
+
+        // The statements up to the next case or the end of the switch
+        let mut body = vec![];
+        let mut scope = 0;
+        loop {
+            let curr = buf.current("Expected '}' for switch statement").clone();
+            match curr.tok_type {
+                TokenType::OpenBrace => scope += 1,
+                TokenType::CloseBrace if scope == 0 => break,
+                TokenType::CloseBrace => scope -= 1,
+                TokenType::Case | TokenType::Default if scope == 0 => break,
+                _ => {}
+            }
+            body.push(curr);
+            buf.advance()
+        }
+        cases.push((DebugSym::new(case_sym_str, label.lineno), value, make_ast(src, &body)))
+    }
+    buf.advance();
+
+    (DebugSym::new(debug_sym_str, lineno), Node::Switch { expr, cases })
+}
+
+fn for_statement(buf: &mut TokenBuffer, src: &String) -> (DebugSym, Node) {
+    let lineno = buf.current("").lineno;
+    let mut debug_sym_str = String::from("for (");
+
+    buf.advance();
+    buf_consume!(buf, (TokenType::OpenParen), src, "Expected '(' after 'for'");
+
+    // Init statement, including its ';'
+    let mut init_toks = vec![];
+    loop {
+        let curr = buf.current("Expected ';' after for loop initializer").clone();
+        buf.advance();
+        let is_end = curr.tok_type == TokenType::Semicolon;
+        init_toks.push(curr);
+        if is_end {
+            break;
+        }
+    }
+    let init = make_ast(src, &init_toks);
+    match init.statements.first() {
+        Some((sym, _)) => debug_sym_str += format!("{} ", sym.val).as_str(),
+        None => debug_sym_str += "; ",
+    }
+
+    let cond = if buf.current("Expected condition or ';'").tok_type == TokenType::Semicolon {
+        None
+    } else {
+        Some(expr_parser(buf, &mut debug_sym_str, src))
+    };
+    buf_consume!(buf, (TokenType::Semicolon), src, "Expected ';' after for loop condition");
+    debug_sym_str += "; ";
+
+    // Step statement, up to the matching ')'
+    let mut step_toks = vec![];
+    let mut scope = 0;
+    loop {
+        let curr = buf.current("Expected ')' after for loop step").clone();
+        if curr.tok_type == TokenType::OpenParen {
+            scope += 1
+        } else if curr.tok_type == TokenType::CloseParen {
+            if scope == 0 {
+                break;
+            }
+            scope -= 1
+        }
+        step_toks.push(curr);
+        buf.advance()
+    }
+    if let Some(last) = step_toks.last().cloned() {
+        step_toks.push(Token {
+            tok_type: TokenType::Semicolon,
+            val: ";".to_string(),
+            ..last
+        })
+    }
+    let step = make_ast(src, &step_toks);
+    if let Some((sym, _)) = step.statements.first() {
+        debug_sym_str += sym.val.trim_end_matches(';')
+    }
+    buf_consume!(buf, (TokenType::CloseParen), src, "Expected ')' after for loop step");
+    debug_sym_str += ")";
+
+    let body = sub_program(buf, src, "for statement");
+
+    (DebugSym::new(debug_sym_str, lineno), Node::For { init, cond, step, body })
+}
+
 fn is_datatype(tok: &Token) -> bool {
     tok.tok_type == TokenType::Void
         || tok.tok_type == TokenType::Int
@@ -415,6 +1247,9 @@ fn is_datatype(tok: &Token) -> bool {
         || tok.tok_type == TokenType::Float
         || tok.tok_type == TokenType::String
         || tok.tok_type == TokenType::Character
+        || tok.tok_type == TokenType::Boolean
+        || tok.tok_type == TokenType::Const
+        || tok.tok_type == TokenType::Identifier
 }
 
 fn sub_program(buf: &mut TokenBuffer, src: &String, err: &str) -> Program {
@@ -1,14 +1,17 @@
-use std::collections::VecDeque;
 use std::fmt::Write;
 use std::process::exit;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use super::{
     super::{
         imports::ImportHelper,
-        linker::{Linker, LinkerFunc},
+        linker::{Linker, LinkerAlias, LinkerEnum, LinkerFunc, LinkerGlobal, LinkerStruct, LinkerUnion},
+        runtime::get_runtime_code,
         strings::StringsContainer,
     },
+    conversions::implicit_conversion,
     nodes::*,
+    patterns::check_match,
 };
 
 pub fn compile_ast(
@@ -17,7 +20,15 @@ pub fn compile_ast(
     linker: &mut Linker,
     importer: &mut ImportHelper,
 ) -> Result<String, std::fmt::Error> {
-    internal_compile_ast(prog, compile_args, linker, &VarStack::new(), &None, &mut StringsContainer::new(), importer)
+    let mut strings = StringsContainer::new();
+    let out = internal_compile_ast(prog, compile_args, linker, &VarStack::new(), &None, &mut strings, importer)?;
+    // Imported modules hand their string literals to the main file
+    if compile_args.standalone {
+        for (label, string) in strings.get_strings() {
+            linker.add_string(&label, &string)
+        }
+    }
+    Ok(out)
 }
 
 fn internal_compile_ast(
@@ -33,69 +44,209 @@ fn internal_compile_ast(
     let mut var_stack = outer_scope.clone();
     var_stack.push_frame();
 
-    if !compile_args.standalone {
-        writeln!(out, "BITS == 32")?;
-        writeln!(out, "MINHEAP 4096")?;
-        writeln!(out, "MINSTACK 1024")?;
-        writeln!(out, "CAL ._Hx4maini8")?;
-        writeln!(out, "HLT\n")?;
-    }
-
     for (sym, stmt) in &prog.statements {
         match stmt {
+            // Outside of functions variables become globals, whose initial value is part of the output
+            Node::VarDefine { typ, ident, expr } if func_args.is_none() => {
+                let typ = &resolve_type(typ, linker, &var_stack, func_args, sym);
+                let mut global = LinkerGlobal {
+                    name: ident.clone(),
+                    typ: typ.clone(),
+                    words: vec![],
+                    strings: vec![],
+                    value: None,
+                };
+                match expr {
+                    Some(expr) => {
+                        global.typ = compile_global_data(typ, expr, &mut global, linker, sym);
+                    }
+                    None => {
+                        if let Type::Arr(_, None) = typ.unalias() {
+                            eprintln!("Error: Array {} needs a length or an initializer at line {}", ident, sym.lineno);
+                            eprintln!("{}: {}", sym.lineno, sym.val);
+                            exit(1)
+                        }
+                        global.words = vec![String::from("0"); linker.size_of(typ) as usize]
+                    }
+                }
+                linker.add_global(&global, true)
+            }
+
             Node::VarDefine { typ, ident, expr } => {
                 if compile_args.debug_symbols {
                     writeln!(out, "// {}: {}", sym.lineno, sym.val)?
                 }
+                let typ = &resolve_type(typ, linker, &var_stack, func_args, sym);
+
+                // Inner scopes can shadow variables, but each scope only gets one of a name, arguments count as the function's scope
+                let is_arg = func_args.as_ref().is_some_and(|args| args.get_offset(ident).is_some());
+                if var_stack.in_current_frame(ident) || (is_arg && var_stack.is_outermost_frame()) {
+                    eprintln!("Error: Redefinition of variable {} at line {}", ident, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+
+                if let Type::Arr(_, None) = typ.unalias() {
+                    eprintln!("Error: Array {} needs a length or an initializer at line {}", ident, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
 
-                var_stack.push(ident.to_string(), typ);
+                let size = linker.size_of(typ);
                 if let Some(expr) = expr {
-                    write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32).unwrap())?;
-                } else {
+                    check_assign(typ, expr, linker, &var_stack, func_args, sym);
+                    write!(out, "{}", compile_init(typ, expr, linker, &var_stack, func_args, strings, sym).unwrap())?;
+                } else if size == 1 {
                     writeln!(out, "DEC SP SP\n")?
+                } else {
+                    writeln!(out, "SUB SP SP {}\n", size)?
                 }
+                var_stack.push(ident.to_string(), typ, size);
             }
 
-            Node::VarAssign { ident, expr } => {
+            Node::VarAssign { lhs, expr } => {
                 if compile_args.debug_symbols {
                     writeln!(out, "// {}: {}", sym.lineno, sym.val)?
                 }
 
+                let typ = get_expr_type(lhs, linker, &var_stack, func_args, sym);
+                if typ.is_const() {
+                    eprintln!("Error: Cannot assign to constant of type {} at line {}", typ, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+                check_assign(&typ, expr, linker, &var_stack, func_args, sym);
+
+                let location = get_location(lhs, linker, &var_stack, func_args, strings, sym);
                 match expr {
-                    Expr::Number(num) => writeln!(out, "IMM R2 {}", num)?,
+                    Expr::Number(num) if !is_float(&typ) && !is_wide(&typ) => {
+                        write!(out, "{}", location.code)?;
+                        writeln!(out, "IMM R2 {}", (wrap_value(*num, &typ) as u64) & 0xffff_ffff)?;
+                        writeln!(out, "LSTR {} {} R2\n", location.base, location.offset)?
+                    }
+
+                    _ => {
+                        write!(out, "{}", compile_init(&typ, expr, linker, &var_stack, func_args, strings, sym).unwrap())?;
+                        write!(out, "{}", compile_store(&location, linker.size_of(&typ)).unwrap())?
+                    }
+                }
+            }
 
-                    _ => write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32).unwrap())?,
+            Node::CompoundAssign { lhs, op, expr } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
                 }
-                if let Some(offset) = var_stack.get_offset(ident) {
-                    writeln!(out, "LSTR R1 -{} R2", offset)?
+
+                let typ = get_expr_type(lhs, linker, &var_stack, func_args, sym);
+                check_update(&typ, linker, sym);
+
+                // Two word values go through a full two word operation, which is narrowed again when only the right side has two words
+                let rhs_type = get_expr_type(expr, linker, &var_stack, func_args, sym);
+                if is_two_words(&typ) || is_two_words(&rhs_type) {
+                    // The left side is only evaluated once, its address is kept in a hidden variable.
+                    // `return` cannot be a variable name, so it does not hide anything.
+                    let location = get_location(lhs, linker, &var_stack, func_args, strings, sym);
+                    write!(out, "{}", location.code)?;
+                    writeln!(out, "ADD R2 {} {}", location.base, location.offset)?;
+                    writeln!(out, "PSH R2")?;
+                    let mut update_scope = var_stack.clone();
+                    update_scope.push_frame();
+                    update_scope.push(String::from("return"), &Type::Ptr(Box::new(typ.clone())), 1);
+                    let target = Expr::Deref(Box::new(Expr::Ident(String::from("return"))));
+
+                    let updated = Expr::BiOp {
+                        lhs: Box::new(target.clone()),
+                        op: *op,
+                        rhs: Box::new(expr.clone()),
+                    };
+                    write!(
+                        out,
+                        "{}",
+                        compile_converted(&updated, &typ, linker, &update_scope, func_args, strings, sym).unwrap()
+                    )?;
+                    let location = get_location(&target, linker, &update_scope, func_args, strings, sym);
+                    write!(out, "{}", compile_store(&location, linker.size_of(&typ)).unwrap())?;
+                    writeln!(out, "ADD SP SP {}\n", update_scope.pop_frame())?
+                } else {
+                    // Literals go straight into the instruction, anything else is evaluated into R4 first
+                    let (rhs, rhs_is_float) = match expr {
+                        Expr::Number(num) if is_float(&typ) => (format!("0x{:x}", (*num as f32).to_bits()), true),
+                        Expr::Number(num) => (((*num as u64) & 0xffff_ffff).to_string(), false),
+                        _ => {
+                            write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32, sym).unwrap())?;
+                            (String::from("R4"), is_float(&get_expr_type(expr, linker, &var_stack, func_args, sym)))
+                        }
+                    };
+
+                    let location = get_location(lhs, linker, &var_stack, func_args, strings, sym);
+                    write!(out, "{}", location.code)?;
+                    if rhs == "R4" {
+                        writeln!(out, "POP R4")?
+                    }
+                    writeln!(out, "LLOD R2 {} {}", location.base, location.offset)?;
+                    write!(out, "{}", compile_update(&typ, op, &rhs, rhs_is_float, linker, sym).unwrap())?;
+                    writeln!(out, "LSTR {} {} R2\n", location.base, location.offset)?
                 }
             }
 
             Node::Function { ret_type, name, args, body } => {
-                let code = internal_compile_ast(
+                let ret_type = &resolve_type(ret_type, linker, &var_stack, func_args, sym);
+                let args = args
+                    .iter()
+                    .map(|(typ, name)| (resolve_type(typ, linker, &var_stack, func_args, sym), name.clone()))
+                    .collect::<Vec<_>>();
+                let arg_types = args.iter().map(|arg| arg.0.clone()).collect::<Vec<_>>();
+                if is_struct(ret_type, linker) {
+                    eprintln!("Error: Cannot return {} by value from function {} at line {}", ret_type, name, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+
+                // Registered before the body is compiled so that the function can call itself
+                let mut func = LinkerFunc::new(ret_type, name, &arg_types, "");
+                linker.add_func(&func, true);
+
+                let mut func_varstack = VarStack::new();
+                func_varstack.push_frame();
+                // Structs and arrays are passed as a pointer, so they take up a single word
+                for (typ, name) in &args {
+                    let size = if is_passed_by_pointer(typ, linker) { 1 } else { linker.size_of(typ) };
+                    func_varstack.push(name.clone(), typ, size)
+                }
+
+                func.code = internal_compile_ast(
                     body,
                     AstCompileArgs {
                         debug_symbols: compile_args.debug_symbols,
                         standalone: true,
                         pop_frame: false,
                         opt_level: compile_args.opt_level,
+                        loop_info: None,
+                        switch_info: None,
+                        ret_type: Some(ret_type),
+                        func_name: Some(name),
                     },
                     linker,
-                    &var_stack,
-                    {
-                        let mut func_varstack = VarStack::new();
-                        func_varstack.push_frame();
-                        args.iter().for_each(|arg| func_varstack.push(arg.1.clone(), &arg.0));
-                        &Some(func_varstack)
-                    },
+                    &VarStack::new(),
+                    &Some(func_varstack),
                     strings,
                     imports,
                 )
                 .unwrap();
 
-                let func = LinkerFunc::new(ret_type, name, &args.iter().map(|arg| arg.0.clone()).collect::<Vec<_>>(), &code);
+                linker.set_func_code(&func.get_signature(), &func.code)
+            }
 
-                linker.add_func(&func, true)
+            Node::Struct { name, fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(typ, name)| (resolve_type(typ, linker, &var_stack, func_args, sym), name.clone()))
+                    .collect::<Vec<_>>();
+                // Also makes sure every field type exists
+                fields.iter().for_each(|(typ, _)| {
+                    linker.size_of(typ);
+                });
+                linker.add_struct(&LinkerStruct { name: name.clone(), fields }, true)
             }
 
             Node::FuncCall { name, args } => {
@@ -103,59 +254,568 @@ fn internal_compile_ast(
                     writeln!(out, "// {}: {}", sym.lineno, sym.val)?
                 }
 
-                fn get_arg_types(args: &Vec<Expr>, var_stack: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) -> Vec<Type> {
-                    let mut res = Vec::new();
-                    for arg in args {
-                        match arg {
-                            Expr::Number(_) => res.push(Type::Named(String::from("int"))),
-
-                            Expr::Ident(ident) => {
-                                let var_type;
-                                if let Some(typ) = var_stack.get_type(ident) {
-                                    var_type = typ
-                                } else if let Some(func_args) = func_args {
-                                    if let Some(typ) = func_args.get_type(ident) {
-                                        var_type = typ
-                                    } else {
-                                        eprintln!("Error: Undefined variable {} at line {}", ident, sym.lineno);
-                                        eprintln!("{}: {}", sym.lineno, sym.val);
-                                        exit(1)
-                                    }
-                                } else {
-                                    eprintln!("Error: Undefined variable {} at line {}", ident, sym.lineno);
-                                    eprintln!("{}: {}", sym.lineno, sym.val);
+                let arg_types = args
+                    .iter()
+                    .map(|arg| get_expr_type(arg, linker, &var_stack, func_args, sym))
+                    .collect::<Vec<_>>();
+                if linker.get_func(name, &arg_types).is_none() {
+                    eprintln!("Error: Undefined function {} at line {}", name, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+                writeln!(out, "{}", compile_call(name, args, linker, &var_stack, func_args, strings, sym).unwrap())?
+            }
+
+            Node::If { cond, body, else_body } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                let id = new_label_id();
+                let block_args = AstCompileArgs {
+                    debug_symbols: compile_args.debug_symbols,
+                    standalone: true,
+                    pop_frame: true,
+                    opt_level: compile_args.opt_level,
+                    loop_info: compile_args.loop_info,
+                    switch_info: compile_args.switch_info,
+                    ret_type: compile_args.ret_type,
+                    func_name: compile_args.func_name,
+                };
+                let else_label = format!(".if{}_else", id);
+                let end_label = format!(".if{}_end", id);
+
+                write!(out, "{}", compile_cond(cond, &else_label, linker, &var_stack, func_args, strings, sym).unwrap())?;
+                write!(
+                    out,
+                    "{}",
+                    internal_compile_ast(body, block_args, linker, &var_stack, func_args, strings, imports).unwrap()
+                )?;
+
+                if let Some(else_body) = else_body {
+                    writeln!(out, "JMP {}", end_label)?;
+                    writeln!(out, "{}", else_label)?;
+                    write!(
+                        out,
+                        "{}",
+                        internal_compile_ast(else_body, block_args, linker, &var_stack, func_args, strings, imports).unwrap()
+                    )?;
+                    writeln!(out, "{}\n", end_label)?
+                } else {
+                    writeln!(out, "{}\n", else_label)?
+                }
+            }
+
+            Node::While { cond, body } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                let id = new_label_id();
+                let end_label = format!(".loop{}_end", id);
+
+                writeln!(out, ".loop{}_continue", id)?;
+                write!(out, "{}", compile_cond(cond, &end_label, linker, &var_stack, func_args, strings, sym).unwrap())?;
+                write!(
+                    out,
+                    "{}",
+                    internal_compile_ast(
+                        body,
+                        AstCompileArgs {
+                            debug_symbols: compile_args.debug_symbols,
+                            standalone: true,
+                            pop_frame: true,
+                            opt_level: compile_args.opt_level,
+                            loop_info: Some(LoopInfo {
+                                id,
+                                stack_size: var_stack.size(),
+                            }),
+                            switch_info: None,
+                            ret_type: compile_args.ret_type,
+                            func_name: compile_args.func_name,
+                        },
+                        linker,
+                        &var_stack,
+                        func_args,
+                        strings,
+                        imports
+                    )
+                    .unwrap()
+                )?;
+                writeln!(out, "JMP .loop{}_continue", id)?;
+                writeln!(out, "{}\n", end_label)?
+            }
+
+            Node::For { init, cond, step, body } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                let id = new_label_id();
+                let end_label = format!(".loop{}_end", id);
+                let block_args = AstCompileArgs {
+                    debug_symbols: compile_args.debug_symbols,
+                    standalone: true,
+                    pop_frame: true,
+                    opt_level: compile_args.opt_level,
+                    loop_info: compile_args.loop_info,
+                    switch_info: None,
+                    ret_type: compile_args.ret_type,
+                    func_name: compile_args.func_name,
+                };
+
+                // The init variable stays on the stack until the loop is left
+                write!(
+                    out,
+                    "{}",
+                    internal_compile_ast(
+                        init,
+                        AstCompileArgs {
+                            pop_frame: false,
+                            ..block_args
+                        },
+                        linker,
+                        &var_stack,
+                        func_args,
+                        strings,
+                        imports
+                    )
+                    .unwrap()
+                )?;
+                let mut loop_scope = var_stack.clone();
+                loop_scope.push_frame();
+                for (_, stmt) in &init.statements {
+                    if let Node::VarDefine { typ, ident, .. } = stmt {
+                        let typ = resolve_type(typ, linker, &var_stack, func_args, sym);
+                        loop_scope.push(ident.clone(), &typ, linker.size_of(&typ))
+                    }
+                }
+
+                writeln!(out, ".loop{}_start", id)?;
+                if let Some(cond) = cond {
+                    write!(out, "{}", compile_cond(cond, &end_label, linker, &loop_scope, func_args, strings, sym).unwrap())?
+                }
+                write!(
+                    out,
+                    "{}",
+                    internal_compile_ast(
+                        body,
+                        AstCompileArgs {
+                            loop_info: Some(LoopInfo {
+                                id,
+                                stack_size: loop_scope.size(),
+                            }),
+                            ..block_args
+                        },
+                        linker,
+                        &loop_scope,
+                        func_args,
+                        strings,
+                        imports
+                    )
+                    .unwrap()
+                )?;
+                writeln!(out, ".loop{}_continue", id)?;
+                write!(
+                    out,
+                    "{}",
+                    internal_compile_ast(step, block_args, linker, &loop_scope, func_args, strings, imports).unwrap()
+                )?;
+                writeln!(out, "JMP .loop{}_start", id)?;
+                writeln!(out, "{}", end_label)?;
+
+                let init_slots = loop_scope.pop_frame();
+                if init_slots > 0 {
+                    writeln!(out, "ADD SP SP {}", init_slots)?
+                }
+                writeln!(out)?
+            }
+
+            Node::DoWhile { body, cond } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                let id = new_label_id();
+                let end_label = format!(".loop{}_end", id);
+
+                writeln!(out, ".loop{}_start", id)?;
+                write!(
+                    out,
+                    "{}",
+                    internal_compile_ast(
+                        body,
+                        AstCompileArgs {
+                            debug_symbols: compile_args.debug_symbols,
+                            standalone: true,
+                            pop_frame: true,
+                            opt_level: compile_args.opt_level,
+                            loop_info: Some(LoopInfo {
+                                id,
+                                stack_size: var_stack.size(),
+                            }),
+                            switch_info: None,
+                            ret_type: compile_args.ret_type,
+                            func_name: compile_args.func_name,
+                        },
+                        linker,
+                        &var_stack,
+                        func_args,
+                        strings,
+                        imports
+                    )
+                    .unwrap()
+                )?;
+                writeln!(out, ".loop{}_continue", id)?;
+                write!(out, "{}", compile_cond(cond, &end_label, linker, &var_stack, func_args, strings, sym).unwrap())?;
+                writeln!(out, "JMP .loop{}_start", id)?;
+                writeln!(out, "{}\n", end_label)?
+            }
+
+            Node::Enum { name, variants } => {
+                let mut values = vec![];
+                let mut next = 0;
+                for (variant, value) in variants {
+                    if let Some(value) = value {
+                        next = match value {
+                            Expr::Number(num) if i32::try_from(*num).is_ok() => *num,
+                            Expr::Char(c) => *c as i64,
+                            _ => {
+                                eprintln!("Error: Value of {}::{} is not a constant int32 at line {}", name, variant, sym.lineno);
+                                eprintln!("{}: {}", sym.lineno, sym.val);
+                                exit(1)
+                            }
+                        }
+                    }
+                    values.push((variant.clone(), next));
+                    next += 1
+                }
+                linker.add_enum(
+                    &LinkerEnum {
+                        name: name.clone(),
+                        variants: values,
+                    },
+                    true,
+                )
+            }
+
+            Node::Union { name, variants } => {
+                let variants = variants
+                    .iter()
+                    .map(|(variant, fields)| {
+                        let fields = fields
+                            .iter()
+                            .map(|(typ, name)| (resolve_type(typ, linker, &var_stack, func_args, sym), name.clone()))
+                            .collect();
+                        (variant.clone(), fields)
+                    })
+                    .collect::<Vec<(String, Vec<(Type, String)>)>>();
+                // Also makes sure every field type exists
+                variants.iter().flat_map(|(_, fields)| fields).for_each(|(typ, _)| {
+                    linker.size_of(typ);
+                });
+                linker.add_union(
+                    &LinkerUnion {
+                        name: name.clone(),
+                        variants,
+                    },
+                    true,
+                )
+            }
+
+            Node::Match { expr, arms } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                let typ = get_expr_type(expr, linker, &var_stack, func_args, sym);
+                let Some(union) = get_union(&typ, linker).cloned() else {
+                    eprintln!("Error: Cannot match on {} as it is not a union at line {}", typ, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                };
+                check_match(&union, arms, sym);
+
+                // The address of the matched value stays on the stack while the arms run.
+                // `match` cannot be a variable name, so it does not hide anything.
+                let mut match_scope = var_stack.clone();
+                match_scope.push_frame();
+                if let Expr::Ident(_) | Expr::Member { .. } | Expr::Index { .. } | Expr::Deref(_) = expr {
+                    let location = get_location(expr, linker, &var_stack, func_args, strings, sym);
+                    write!(out, "{}", location.code)?;
+                    writeln!(out, "ADD R2 {} {}", location.base, location.offset)?
+                } else {
+                    // Temporaries are kept on the stack below their address
+                    write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32, sym).unwrap())?;
+                    writeln!(out, "MOV R2 SP")?;
+                    match_scope.push(String::from("match"), &typ, linker.size_of(&typ))
+                }
+                writeln!(out, "PSH R2")?;
+                match_scope.push(String::from("match"), &Type::Ptr(Box::new(typ.clone())), 1);
+                let addr_offset = match_scope.size();
+
+                let id = new_label_id();
+                writeln!(out, "LOD R2 R2")?;
+                for (idx, (_, pattern, _)) in arms.iter().enumerate() {
+                    match pattern {
+                        Pattern::Variant { name, .. } => {
+                            let (tag, _) = union.get_variant(name).unwrap();
+                            writeln!(out, "BRE .match{}_arm{} R2 {}", id, idx, tag)?
+                        }
+                        Pattern::Wildcard => writeln!(out, "JMP .match{}_arm{}", id, idx)?,
+                    }
+                }
+                writeln!(out, "JMP .match{}_end", id)?;
+
+                for (idx, (arm_sym, pattern, body)) in arms.iter().enumerate() {
+                    writeln!(out, ".match{}_arm{}", id, idx)?;
+
+                    // Bound fields are copies, which live in their own scope around the arm
+                    let mut arm_scope = match_scope.clone();
+                    arm_scope.push_frame();
+                    if let Pattern::Variant {
+                        name,
+                        bindings: Some(bindings),
+                    } = pattern
+                    {
+                        let (_, fields) = union.get_variant(name).unwrap();
+                        let mut field_offset = 1;
+                        for (binding, (field_type, _)) in bindings.iter().zip(fields) {
+                            let size = linker.size_of(field_type);
+                            if binding != "_" {
+                                if arm_scope.in_current_frame(binding) {
+                                    eprintln!("Error: {} is bound more than once at line {}", binding, arm_sym.lineno);
+                                    eprintln!("{}: {}", arm_sym.lineno, arm_sym.val);
                                     exit(1)
                                 }
-                                res.push(var_type)
+                                writeln!(out, "LLOD R3 R1 -{}", addr_offset)?;
+                                for word in (0..size).rev() {
+                                    writeln!(out, "LLOD R2 R3 {}", field_offset + word)?;
+                                    writeln!(out, "PSH R2")?
+                                }
+                                arm_scope.push(binding.clone(), field_type, size)
                             }
+                            field_offset += size
+                        }
+                    }
+
+                    write!(
+                        out,
+                        "{}",
+                        internal_compile_ast(
+                            body,
+                            AstCompileArgs {
+                                standalone: true,
+                                pop_frame: true,
+                                ..compile_args
+                            },
+                            linker,
+                            &arm_scope,
+                            func_args,
+                            strings,
+                            imports
+                        )
+                        .unwrap()
+                    )?;
+                    let bound = arm_scope.pop_frame();
+                    if bound > 0 {
+                        writeln!(out, "ADD SP SP {}", bound)?
+                    }
+                    writeln!(out, "JMP .match{}_end", id)?
+                }
+                writeln!(out, ".match{}_end", id)?;
+                writeln!(out, "ADD SP SP {}\n", match_scope.pop_frame())?
+            }
+
+            Node::Switch { expr, cases } => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                let typ = get_expr_type(expr, linker, &var_stack, func_args, sym);
+                let enum_type = get_enum(&typ, linker).cloned();
+                if enum_type.is_none() && !is_bool(&typ) && (typ.int_info().is_none() || is_wide(&typ)) {
+                    eprintln!("Error: Cannot switch on {} at line {}", typ, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
 
-                            _ => todo!(),
+                // Case values as the scrutinee holds them, together with the index of their case
+                let mut values: Vec<(i64, usize)> = vec![];
+                for (idx, (case_sym, value, _)) in cases.iter().enumerate() {
+                    if let Some(value) = value {
+                        let num = get_case_value(value, &typ, &enum_type, linker, &var_stack, func_args, case_sym);
+                        if values.iter().any(|(other, _)| *other == num) {
+                            eprintln!("Error: Duplicate case at line {}", case_sym.lineno);
+                            eprintln!("{}: {}", case_sym.lineno, case_sym.val);
+                            exit(1)
                         }
+                        values.push((num, idx))
                     }
-                    res
                 }
-                let arg_types = get_arg_types(args, &var_stack, func_args, sym);
-                let func = linker.get_func(name, &arg_types);
-                if let Some(func) = func {
-                    for arg in args {
-                        write!(out, "{}", compile_expr(arg, linker, &var_stack, func_args, strings, 32).unwrap())?
+
+                if let (Some(enum_type), false) = (&enum_type, cases.iter().any(|(_, value, _)| value.is_none())) {
+                    let missing = enum_type
+                        .variants
+                        .iter()
+                        .filter(|(_, value)| !values.iter().any(|(num, _)| num == value))
+                        .map(|(variant, _)| variant.clone())
+                        .collect::<Vec<_>>();
+                    if !missing.is_empty() {
+                        eprintln!("Warning: Switch on {} does not handle {} at line {}", typ, missing.join(", "), sym.lineno);
+                        eprintln!("{}: {}", sym.lineno, sym.val)
                     }
-                    writeln!(out, "CAL .{}", func.get_signature())?;
+                }
 
-                    let args_len = args.len();
-                    if args_len > 0 {
-                        writeln!(out, "ADD SP SP {}", args_len)?
+                let id = new_label_id();
+                let end_label = format!(".switch{}_end", id);
+                let default_label = match cases.iter().position(|(_, value, _)| value.is_none()) {
+                    Some(idx) => format!(".switch{}_case{}", id, idx),
+                    None => end_label.clone(),
+                };
+
+                write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32, sym).unwrap())?;
+                writeln!(out, "POP R2")?;
+
+                // Dense cases jump through a table of case labels indexed by the value, sparse ones compare one by one
+                let min = values.iter().map(|(num, _)| *num).min().unwrap_or(0);
+                let max = values.iter().map(|(num, _)| *num).max().unwrap_or(0);
+                let range = (max - min + 1) as usize;
+                if values.len() >= 4 && range <= values.len() * 2 {
+                    if min != 0 {
+                        writeln!(out, "SUB R2 R2 {}", (min as u64) & 0xffff_ffff)?
                     }
+                    // Values below the smallest case wrap around and fail this too
+                    writeln!(out, "BGE {} R2 {}", default_label, range)?;
+                    writeln!(out, "ADD R2 R2 .switch{}_table", id)?;
+                    writeln!(out, "LOD R2 R2")?;
+                    writeln!(out, "JMP R2")?;
+                    let table = (min..=max)
+                        .map(|num| match values.iter().find(|(other, _)| *other == num) {
+                            Some((_, idx)) => format!(".switch{}_case{}", id, idx),
+                            None => default_label.clone(),
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(out, ".switch{}_table", id)?;
+                    writeln!(out, "DW [ {} ]", table.join(" "))?
                 } else {
-                    eprintln!("Error: Undefined function {} at line {}", name, sym.lineno);
+                    for (num, idx) in &values {
+                        writeln!(out, "BRE .switch{}_case{} R2 {}", id, idx, (*num as u64) & 0xffff_ffff)?
+                    }
+                    writeln!(out, "JMP {}", default_label)?
+                }
+
+                for (idx, (_, _, body)) in cases.iter().enumerate() {
+                    writeln!(out, ".switch{}_case{}", id, idx)?;
+                    write!(
+                        out,
+                        "{}",
+                        internal_compile_ast(
+                            body,
+                            AstCompileArgs {
+                                standalone: true,
+                                pop_frame: true,
+                                switch_info: Some(LoopInfo {
+                                    id,
+                                    stack_size: var_stack.size(),
+                                }),
+                                ..compile_args
+                            },
+                            linker,
+                            &var_stack,
+                            func_args,
+                            strings,
+                            imports
+                        )
+                        .unwrap()
+                    )?
+                }
+                writeln!(out, "{}\n", end_label)?
+            }
+
+            Node::Block(body) => write!(
+                out,
+                "{}",
+                internal_compile_ast(
+                    body,
+                    AstCompileArgs {
+                        standalone: true,
+                        pop_frame: true,
+                        ..compile_args
+                    },
+                    linker,
+                    &var_stack,
+                    func_args,
+                    strings,
+                    imports
+                )
+                .unwrap()
+            )?,
+
+            Node::Break | Node::Continue => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                // `break` leaves a switch before the loop around it
+                let (target, label) = match (stmt, compile_args.switch_info) {
+                    (Node::Break, Some(switch_info)) => (Some(switch_info), format!(".switch{}_end", switch_info.id)),
+                    (Node::Break, None) => (compile_args.loop_info, format!(".loop{}_end", compile_args.loop_info.map_or(0, |info| info.id))),
+                    _ => (
+                        compile_args.loop_info,
+                        format!(".loop{}_continue", compile_args.loop_info.map_or(0, |info| info.id)),
+                    ),
+                };
+                let Some(target) = target else {
+                    eprintln!("Error: '{}' outside of a loop at line {}", sym.val.trim_end_matches(';'), sym.lineno);
                     eprintln!("{}: {}", sym.lineno, sym.val);
                     exit(1)
+                };
+
+                // Drop everything the loop or switch body pushed before leaving it
+                let slots = var_stack.size() - target.stack_size;
+                if slots > 0 {
+                    writeln!(out, "ADD SP SP {}", slots)?
                 }
+                writeln!(out, "JMP {}\n", label)?
             }
 
             Node::Return(expr) => {
+                if compile_args.debug_symbols {
+                    writeln!(out, "// {}: {}", sym.lineno, sym.val)?
+                }
+
+                if let (Some(ret_type), Some(name)) = (compile_args.ret_type, compile_args.func_name) {
+                    let is_void = *ret_type == Type::Named(String::from("void"));
+                    if is_void && expr.is_some() {
+                        eprintln!("Error: Function {} returns void and cannot return a value at line {}", name, sym.lineno);
+                        eprintln!("{}: {}", sym.lineno, sym.val);
+                        exit(1)
+                    }
+                    if !is_void && expr.is_none() {
+                        eprintln!("Error: Function {} must return a value of type {} at line {}", name, ret_type, sym.lineno);
+                        eprintln!("{}: {}", sym.lineno, sym.val);
+                        exit(1)
+                    }
+                }
+
+                // Return values are passed back in R2, two word ones with their high word in R3
                 if let Some(expr) = expr {
-                    write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32).unwrap())?
+                    match compile_args.ret_type {
+                        Some(ret_type) => {
+                            check_assign(ret_type, expr, linker, &var_stack, func_args, sym);
+                            write!(out, "{}", compile_converted(expr, ret_type, linker, &var_stack, func_args, strings, sym).unwrap())?
+                        }
+                        None => write!(out, "{}", compile_expr(expr, linker, &var_stack, func_args, strings, 32, sym).unwrap())?,
+                    }
+                    writeln!(out, "POP R2")?;
+                    let typ = match compile_args.ret_type {
+                        Some(ret_type) => ret_type.clone(),
+                        None => get_expr_type(expr, linker, &var_stack, func_args, sym),
+                    };
+                    if is_two_words(&typ) {
+                        writeln!(out, "POP R3")?
+                    }
                 }
                 // cdecl exit
                 writeln!(out, "MOV SP R1")?;
@@ -163,6 +823,20 @@ fn internal_compile_ast(
                 writeln!(out, "RET")?
             }
 
+            Node::Typedef { name, typ } => {
+                let builtin = matches!(name.as_str(), "void" | "int" | "float32" | "float64" | "string" | "char" | "bool")
+                    || Type::Named(name.clone()).int_info().is_some();
+                if builtin {
+                    eprintln!("Error: Cannot redefine builtin type {} at line {}", name, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+                let typ = resolve_type(typ, linker, &var_stack, func_args, sym);
+                // Also makes sure the aliased type exists
+                linker.size_of(&typ);
+                linker.add_alias(&LinkerAlias { name: name.clone(), typ }, true)
+            }
+
             Node::Import(name) => imports.import(name, compile_args, linker, sym),
 
             Node::InlineURCL(urcl) => {
@@ -171,8 +845,6 @@ fn internal_compile_ast(
                 }
                 writeln!(out, "{}\n", urcl)?
             }
-
-            _ => todo!(),
         }
     }
 
@@ -182,6 +854,17 @@ fn internal_compile_ast(
     }
 
     if !compile_args.standalone {
+        // Top level constants named after a header directive replace its default, e.g. `const uint32 MINHEAP = 64 * 1024;`
+        let directive = |name: &str, default: i64| linker.get_global(name).and_then(|global| global.value).unwrap_or(default);
+        let mut header = String::new();
+        writeln!(header, "BITS == 32")?;
+        writeln!(header, "MINREG 8")?;
+        writeln!(header, "MINHEAP {}", directive("MINHEAP", 4096))?;
+        writeln!(header, "MINSTACK {}", directive("MINSTACK", 1024))?;
+        writeln!(header, "CAL ._Hx4maini8")?;
+        writeln!(header, "HLT\n")?;
+        out.insert_str(0, &header);
+
         for func in linker.get_funcs() {
             writeln!(out, ".{}", func.get_signature())?;
 
@@ -198,9 +881,24 @@ fn internal_compile_ast(
             writeln!(out)?
         }
 
-        for (idx, string) in strings.get_strings().iter().enumerate() {
-            writeln!(out, ".str{}", idx)?;
-            writeln!(out, "DW [ \"{}\" ]", string)?
+        for name in linker.get_runtime() {
+            writeln!(out, "{}", get_runtime_code(name))?
+        }
+
+        for global in linker.get_globals() {
+            writeln!(out, "{}", global.get_label())?;
+            if !global.words.is_empty() {
+                writeln!(out, "DW [ {} ]", global.words.join(" "))?
+            }
+            for (idx, string) in global.strings.iter().enumerate() {
+                writeln!(out, "{}_str{}", global.get_label(), idx)?;
+                writeln!(out, "DW [ {} 0 ]", urcl_string(string))?
+            }
+        }
+
+        for (label, string) in linker.get_strings().iter().cloned().chain(strings.get_strings()) {
+            writeln!(out, "{}", label)?;
+            writeln!(out, "DW [ {} 0 ]", urcl_string(&string))?
         }
     }
 
@@ -209,204 +907,2042 @@ fn internal_compile_ast(
 
 fn compile_expr(
     expr: &Expr,
-    _linker: &mut Linker,
+    linker: &mut Linker,
     vars: &VarStack,
     func_args: &Option<VarStack>,
     strings: &mut StringsContainer,
     max: u32,
+    sym: &DebugSym,
 ) -> Result<String, std::fmt::Error> {
     let mut s = String::new();
     let max = 1u64.checked_shl(max).unwrap_or(0).wrapping_sub(1);
 
     match expr {
-        Expr::Number(num) => writeln!(s, "PSH {}\n", (*num as u64) % max)?,
+        Expr::Number(num) => writeln!(s, "PSH {}\n", (*num as u64) & max)?,
+        Expr::Float(val) => writeln!(s, "PSH 0x{:x}\n", (*val as f32).to_bits())?,
+        Expr::Char(c) => writeln!(s, "PSH {}\n", *c as u32)?,
+        Expr::Bool(val) => writeln!(s, "PSH {}\n", *val as u32)?,
 
-        Expr::Ident(name) => match vars.get_offset(name) {
-            Some(offset) => {
-                writeln!(s, "LLOD R2 R1 -{}", offset)?;
+        Expr::Ident(_) | Expr::Member { .. } | Expr::Index { .. } | Expr::Deref(_) => {
+            let size = linker.size_of(&get_expr_type(expr, linker, vars, func_args, sym));
+            let location = get_location(expr, linker, vars, func_args, strings, sym);
+
+            write!(s, "{}", location.code)?;
+            // Highest word first, so that the first word ends up at the lowest address
+            for word in (0..size).rev() {
+                writeln!(s, "LLOD R2 {} {}", location.base, location.offset + word as i64)?;
                 writeln!(s, "PSH R2")?
             }
+        }
 
-            None => {
-                if let Some(func_args) = func_args {
-                    if let Some(offset) = func_args.get_offset(name) {
-                        writeln!(s, "LLOD R2 R1 {}", offset + 1)?;
-                        writeln!(s, "PSH R2")?
-                    }
-                    eprintln!()
-                }
-            }
-        },
+        Expr::EnumValue { name, variant } if linker.get_union(name).is_some() => {
+            write!(s, "{}", compile_union_value(name, variant, &[], linker, vars, func_args, strings, sym).unwrap())?
+        }
+
+        Expr::UnionValue { name, variant, args } => {
+            write!(s, "{}", compile_union_value(name, variant, args, linker, vars, func_args, strings, sym).unwrap())?
+        }
+
+        Expr::EnumValue { name, variant } => {
+            get_expr_type(expr, linker, vars, func_args, sym);
+            let value = linker.get_enum(name).unwrap().get_value(variant).unwrap();
+            writeln!(s, "PSH {}\n", (value as u64) & 0xffff_ffff)?
+        }
 
         Expr::Str(value) => {
-            writeln!(s, "LOD R2 .str{}", strings.register_string(value.clone()))?;
-            writeln!(s, "PSH R2")?
+            // Strings are the address of their zero terminated characters
+            writeln!(s, "PSH {}\n", strings.register_string(value.clone()))?
         }
 
-        Expr::BiOp { .. } => {
-            fn get_op_str(op: &Operation) -> &'static str {
-                match op {
-                    Operation::Add => "ADD",
-                    Operation::Sub => "SUB",
-                    Operation::Mult => "MLT",
-                    Operation::Div => "DIV",
-                    Operation::Mod => "MOD",
-                }
+        // Operands are evaluated onto the stack, so arbitrarily nested expressions never fight over registers
+        Expr::BiOp { lhs, op, rhs } => {
+            let lhs_type = operand_type(lhs, linker, vars, func_args, sym);
+            let rhs_type = operand_type(rhs, linker, vars, func_args, sym);
+            if let Some(enum_type) = get_enum(&lhs_type, linker).or(get_enum(&rhs_type, linker)) {
+                eprintln!(
+                    "Error: Invalid operation {:?} on enum {}, it needs a cast to an integer first at line {}",
+                    op, enum_type.name, sym.lineno
+                );
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
             }
-            fn compile_expr_recursive(
-                expr: &Expr,
-                reg_count: u64,
-                vars: &VarStack,
-                func_args: &Option<VarStack>,
-                instr_queue: &mut VecDeque<String>,
-            ) -> Result<String, std::fmt::Error> {
-                let mut ret = String::new();
-                let mut reg_count = reg_count;
 
-                if let Expr::BiOp { lhs, op, rhs } = expr {
-                    write!(ret, "{} R{} ", get_op_str(op), reg_count)?;
+            // Mixed integer and float operands are computed as floats, float64s by the runtime
+            if is_float(&lhs_type) || is_float(&rhs_type) {
+                let float_type = common_float_type(&lhs_type, &rhs_type);
+                write!(s, "{}", compile_converted(lhs, &float_type, linker, vars, func_args, strings, sym).unwrap())?;
+                write!(s, "{}", compile_converted(rhs, &float_type, linker, vars, func_args, strings, sym).unwrap())?;
+                let (op_str, routine) = match op {
+                    Operation::Add => ("FADD", "rt_fadd64"),
+                    Operation::Sub => ("FSUB", "rt_fsub64"),
+                    Operation::Mult => ("FMLT", "rt_fmul64"),
+                    Operation::Div => ("FDIV", "rt_fdiv64"),
+                    _ => {
+                        eprintln!(
+                            "Error: Invalid operation {:?} on float types {} and {} at line {}",
+                            op, lhs_type, rhs_type, sym.lineno
+                        );
+                        eprintln!("{}: {}", sym.lineno, sym.val);
+                        exit(1)
+                    }
+                };
+                if is_float64(&float_type) {
+                    write!(s, "{}", compile_runtime_call(routine, 4, linker).unwrap())?;
+                    writeln!(s, "PSH R3")?
+                } else {
+                    writeln!(s, "POP R3")?;
+                    writeln!(s, "POP R2")?;
+                    writeln!(s, "{} R2 R2 R3", op_str)?
+                }
+                writeln!(s, "PSH R2\n")?;
+                return Ok(s);
+            }
 
-                    // LHS
-                    {
-                        let lhs1 = lhs;
-                        match *lhs1.clone() {
-                            Expr::Number(num) => write!(ret, "{} ", num)?,
-
-                            Expr::Ident(name) => {
-                                if let Some(offset) = vars.get_offset(&name) {
-                                    instr_queue.push_back(format!(
-                                        "LLOD R{} R1 -{}\n",
-                                        {
-                                            reg_count += 1;
-                                            reg_count
-                                        },
-                                        offset
-                                    ))
-                                } else if let Some(func_args) = func_args {
-                                    if let Some(offset) = func_args.get_offset(&name) {
-                                        instr_queue.push_back(format!(
-                                            "LLOD R{} R1 {}\n",
-                                            {
-                                                reg_count += 1;
-                                                reg_count
-                                            },
-                                            offset + 1
-                                        ))
-                                    }
-                                } else {
-                                    eprintln!("Error: Undefined variable {}", name);
-                                    exit(1)
-                                }
-                                write!(ret, "R{} ", reg_count)?
-                            }
+            // 64 bit operands are combined word by word or handed to the runtime, shift amounts stay a single word
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            if is_wide(&typ) {
+                write!(s, "{}", compile_converted(lhs, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+                if let Operation::Shl | Operation::Shr = op {
+                    write!(
+                        s,
+                        "{}",
+                        compile_converted(rhs, &Type::Named(String::from("uint32")), linker, vars, func_args, strings, sym).unwrap()
+                    )?
+                } else {
+                    write!(s, "{}", compile_converted(rhs, &typ, linker, vars, func_args, strings, sym).unwrap())?
+                }
+                write!(s, "{}", compile_wide_op(op, is_signed(&typ), linker).unwrap())?;
+                return Ok(s);
+            }
 
-                            Expr::Str(_) => todo!(),
-
-                            Expr::BiOp { .. } => {
-                                let code = compile_expr_recursive(
-                                    lhs1,
-                                    {
-                                        reg_count += 1;
-                                        reg_count
-                                    },
-                                    vars,
-                                    func_args,
-                                    instr_queue,
-                                )
-                                .unwrap();
-                                instr_queue.push_back(code);
-                                write!(ret, "R{} ", reg_count)?
-                            }
+            // Only shift amounts and pointer offsets can still be 64 bit here, both are used as a single word
+            for (side, side_type) in [(lhs, &lhs_type), (rhs, &rhs_type)] {
+                if is_wide(side_type) {
+                    write!(
+                        s,
+                        "{}",
+                        compile_converted(side, &Type::Named(String::from("int32")), linker, vars, func_args, strings, sym).unwrap()
+                    )?
+                } else {
+                    write!(s, "{}", compile_expr(side, linker, vars, func_args, strings, 32, sym).unwrap())?
+                }
+            }
+            writeln!(s, "POP R3")?;
+            writeln!(s, "POP R2")?;
 
-                            _ => todo!(),
-                        }
+            // Pointer arithmetic moves in steps of the pointed to type
+            match (op, &lhs_type, &rhs_type) {
+                (Operation::Sub, Type::Ptr(typ), Type::Ptr(_)) => {
+                    writeln!(s, "SUB R2 R2 R3")?;
+                    let size = linker.size_of(typ);
+                    if size > 1 {
+                        writeln!(s, "SDIV R2 R2 {}", size)?
+                    }
+                }
+                (Operation::Add | Operation::Sub, Type::Ptr(typ), _) => {
+                    let size = linker.size_of(typ);
+                    if size > 1 {
+                        writeln!(s, "MLT R3 R3 {}", size)?
+                    }
+                    writeln!(s, "{} R2 R2 R3", get_op_str(op))?
+                }
+                (Operation::Add, _, Type::Ptr(typ)) => {
+                    let size = linker.size_of(typ);
+                    if size > 1 {
+                        writeln!(s, "MLT R2 R2 {}", size)?
+                    }
+                    writeln!(s, "ADD R2 R2 R3")?
+                }
+                (_, Type::Ptr(_), _) | (_, _, Type::Ptr(_)) => {
+                    eprintln!(
+                        "Error: Invalid operation {:?} on pointer types {} and {} at line {}",
+                        op, lhs_type, rhs_type, sym.lineno
+                    );
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+                _ => {
+                    // Both operands are brought to the type of the result first, shift amounts are left alone
+                    if lhs_type != typ {
+                        write!(s, "{}", compile_wrap(&typ, "R2").unwrap())?
+                    }
+                    if rhs_type != typ && !matches!(op, Operation::Shl | Operation::Shr) {
+                        write!(s, "{}", compile_wrap(&typ, "R3").unwrap())?
                     }
+                    write!(s, "{}", compile_int_op(op, is_signed(&typ), "R3").unwrap())?;
+                    write!(s, "{}", compile_wrap(&typ, "R2").unwrap())?
+                }
+            }
 
-                    // RHS
-                    {
-                        let rhs1 = rhs;
-                        match *rhs1.clone() {
-                            Expr::Number(num) => writeln!(ret, "{}", num)?,
-
-                            Expr::Ident(name) => {
-                                if let Some(offset) = vars.get_offset(&name) {
-                                    instr_queue.push_back(format!(
-                                        "LLOD R{} R1 -{}\n",
-                                        {
-                                            reg_count += 1;
-                                            reg_count
-                                        },
-                                        offset
-                                    ))
-                                } else if let Some(func_args) = func_args {
-                                    if let Some(offset) = func_args.get_offset(&name) {
-                                        instr_queue.push_back(format!(
-                                            "LLOD R{} R1 {}\n",
-                                            {
-                                                reg_count += 1;
-                                                reg_count
-                                            },
-                                            offset + 1
-                                        ))
-                                    }
-                                } else {
-                                    eprintln!("Error: Undefined variable {}", name);
-                                    exit(1)
-                                }
-                                writeln!(ret, "R{}", reg_count)?
-                            }
+            writeln!(s, "PSH R2\n")?
+        }
 
-                            Expr::Str(_) => todo!(),
-
-                            Expr::BiOp { .. } => {
-                                let code = compile_expr_recursive(
-                                    rhs1,
-                                    {
-                                        reg_count += 1;
-                                        reg_count
-                                    },
-                                    vars,
-                                    func_args,
-                                    instr_queue,
-                                )
-                                .unwrap();
-                                instr_queue.push_back(code);
-                                writeln!(ret, "R{}", reg_count)?
-                            }
+        Expr::Comp { lhs, comp, rhs } => {
+            let (operands, signed) = compile_comp_operands(lhs, rhs, linker, vars, func_args, strings, sym)?;
+            write!(s, "{}", operands)?;
+            // SETxx gives all ones for true, normalize it to 1
+            writeln!(s, "{} R2 R2 R3", get_comp_set_str(comp, signed))?;
+            writeln!(s, "AND R2 R2 1")?;
+            writeln!(s, "PSH R2\n")?
+        }
 
-                            _ => todo!(),
-                        }
-                    }
+        Expr::IncDec { expr: inner, op, prefix } => {
+            let typ = get_expr_type(inner, linker, vars, func_args, sym);
+            check_update(&typ, linker, sym);
+
+            // Two word values are updated in registers, the low word carries into the high word when it wraps around.
+            // SETE gives -1 for a carry, which is subtracted from the high word.
+            if is_wide(&typ) {
+                let location = get_location(inner, linker, vars, func_args, strings, sym);
+                write!(s, "{}", location.code)?;
+                writeln!(s, "LLOD R2 {} {}", location.base, location.offset)?;
+                writeln!(s, "LLOD R4 {} {}", location.base, location.offset + 1)?;
+                if let Operation::Add = op {
+                    writeln!(s, "ADD R5 R2 1")?;
+                    writeln!(s, "SETE R6 R5 0")?;
+                    writeln!(s, "SUB R7 R4 R6")?
+                } else {
+                    writeln!(s, "SETE R6 R2 0")?;
+                    writeln!(s, "SUB R5 R2 1")?;
+                    writeln!(s, "ADD R7 R4 R6")?
+                }
+                writeln!(s, "LSTR {} {} R5", location.base, location.offset)?;
+                writeln!(s, "LSTR {} {} R7", location.base, location.offset + 1)?;
+                if *prefix {
+                    writeln!(s, "PSH R7")?;
+                    writeln!(s, "PSH R5\n")?
+                } else {
+                    writeln!(s, "PSH R4")?;
+                    writeln!(s, "PSH R2\n")?
                 }
+                return Ok(s);
+            }
 
-                Ok(ret)
+            // The runtime clobbers every register but R1, so the address of a float64 is kept on the stack meanwhile
+            if is_float64(&typ) {
+                let location = get_location(inner, linker, vars, func_args, strings, sym);
+                write!(s, "{}", location.code)?;
+                writeln!(s, "ADD R2 {} {}", location.base, location.offset)?;
+                writeln!(s, "LLOD R4 R2 1")?;
+                writeln!(s, "LOD R5 R2")?;
+                if !prefix {
+                    writeln!(s, "PSH R4")?;
+                    writeln!(s, "PSH R5")?
+                }
+                writeln!(s, "PSH R2")?;
+                writeln!(s, "PSH R4")?;
+                writeln!(s, "PSH R5")?;
+                writeln!(s, "PSH 0x{:x}", 1f64.to_bits() >> 32)?;
+                writeln!(s, "PSH 0")?;
+                let routine = if let Operation::Add = op { "rt_fadd64" } else { "rt_fsub64" };
+                write!(s, "{}", compile_runtime_call(routine, 4, linker).unwrap())?;
+                writeln!(s, "POP R4")?;
+                writeln!(s, "STR R4 R2")?;
+                writeln!(s, "LSTR R4 1 R3")?;
+                if *prefix {
+                    writeln!(s, "PSH R3")?;
+                    writeln!(s, "PSH R2")?
+                }
+                writeln!(s)?;
+                return Ok(s);
             }
 
-            let mut instr_queue = VecDeque::<String>::new();
-            let expr_str = compile_expr_recursive(expr, 2, vars, func_args, &mut instr_queue).unwrap();
+            let step = if is_float(&typ) {
+                format!("0x{:x}", 1f32.to_bits())
+            } else {
+                String::from("1")
+            };
 
-            while !instr_queue.is_empty() {
-                let code = instr_queue.pop_front().unwrap();
-                write!(s, "{}", code)?;
+            let location = get_location(inner, linker, vars, func_args, strings, sym);
+            write!(s, "{}", location.code)?;
+            writeln!(s, "LLOD R2 {} {}", location.base, location.offset)?;
+            if !prefix {
+                writeln!(s, "PSH R2")?
+            }
+            write!(s, "{}", compile_update(&typ, op, &step, is_float(&typ), linker, sym).unwrap())?;
+            writeln!(s, "LSTR {} {} R2", location.base, location.offset)?;
+            if *prefix {
+                writeln!(s, "PSH R2")?
             }
+            writeln!(s)?
+        }
 
-            write!(s, "{}", expr_str)?;
-            writeln!(s, "AND R2 R2 0x{:x}", max)?;
-            writeln!(s, "PSH R2\n")?
+        // Goes through the branches of `compile_cond` to short circuit
+        Expr::Logic { .. } => {
+            let id = new_label_id();
+            let false_label = format!(".logic{}_false", id);
+            write!(s, "{}", compile_cond(expr, &false_label, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, "PSH 1")?;
+            writeln!(s, "JMP .logic{}_end", id)?;
+            writeln!(s, "{}", false_label)?;
+            writeln!(s, "PSH 0")?;
+            writeln!(s, ".logic{}_end\n", id)?
         }
 
-        _ => todo!(),
-    }
+        // Known at compile time, the operand of `sizeof` is never evaluated
+        Expr::SizeOf(_) | Expr::SizeOfType(_) | Expr::AlignOf(_) => writeln!(s, "PSH {}\n", const_eval(expr, linker, vars, func_args, sym).unwrap())?,
 
-    Ok(s)
-}
+        // Both branches leave a value of the common type on the stack
+        Expr::Ternary { cond, then, otherwise } => {
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            let id = new_label_id();
+            let else_label = format!(".ternary{}_else", id);
+            write!(s, "{}", compile_cond(cond, &else_label, linker, vars, func_args, strings, sym).unwrap())?;
+            write!(s, "{}", compile_converted(then, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, "JMP .ternary{}_end", id)?;
+            writeln!(s, "{}", else_label)?;
+            write!(s, "{}", compile_converted(otherwise, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, ".ternary{}_end\n", id)?
+        }
+
+        Expr::Neg(inner) => {
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            write!(s, "{}", compile_converted(inner, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, "POP R2")?;
+            if is_wide(&typ) {
+                // The high word is inverted and takes the carry of negating the low word
+                writeln!(s, "POP R3")?;
+                writeln!(s, "SETE R4 R2 0")?;
+                writeln!(s, "NEG R2 R2")?;
+                writeln!(s, "NOT R3 R3")?;
+                writeln!(s, "SUB R3 R3 R4")?;
+                writeln!(s, "PSH R3")?
+            } else if is_float64(&typ) {
+                // Floats only need their sign bit flipped, which is in the high word of a float64
+                writeln!(s, "POP R3")?;
+                writeln!(s, "XOR R3 R3 0x80000000")?;
+                writeln!(s, "PSH R3")?
+            } else if is_float(&typ) {
+                // Floats only need their sign bit flipped
+                writeln!(s, "XOR R2 R2 0x80000000")?
+            } else {
+                writeln!(s, "NEG R2 R2")?;
+                write!(s, "{}", compile_wrap(&typ, "R2").unwrap())?
+            }
+            writeln!(s, "PSH R2\n")?
+        }
+
+        Expr::BitNot(inner) => {
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            write!(s, "{}", compile_converted(inner, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, "POP R2")?;
+            if is_wide(&typ) {
+                writeln!(s, "POP R3")?;
+                writeln!(s, "NOT R3 R3")?;
+                writeln!(s, "PSH R3")?
+            }
+            writeln!(s, "NOT R2 R2")?;
+            write!(s, "{}", compile_wrap(&typ, "R2").unwrap())?;
+            writeln!(s, "PSH R2\n")?
+        }
+
+        Expr::Not(inner) => {
+            check_condition(&get_expr_type(inner, linker, vars, func_args, sym), sym);
+            write!(s, "{}", compile_expr(inner, linker, vars, func_args, strings, 32, sym).unwrap())?;
+            write!(s, "{}", compile_pop_truth(&get_expr_type(inner, linker, vars, func_args, sym)).unwrap())?;
+            writeln!(s, "SETE R2 R2 0")?;
+            writeln!(s, "AND R2 R2 1")?;
+            writeln!(s, "PSH R2\n")?
+        }
+
+        // The conversion itself is the same as an implicit one, casts only allow more of them
+        Expr::Cast { expr: inner, typ } => {
+            let typ = &resolve_type(typ, linker, vars, func_args, sym);
+            check_cast(&get_expr_type(inner, linker, vars, func_args, sym), typ, linker, sym);
+            write!(s, "{}", compile_converted(inner, typ, linker, vars, func_args, strings, sym).unwrap())?
+        }
+
+        Expr::AddrOf(inner) => {
+            let location = get_location(inner, linker, vars, func_args, strings, sym);
+            write!(s, "{}", location.code)?;
+            writeln!(s, "ADD R2 {} {}", location.base, location.offset)?;
+            writeln!(s, "PSH R2\n")?
+        }
+
+        Expr::Array(items) => {
+            // Last element first, so that the first one ends up at the lowest address
+            for item in items.iter().rev() {
+                write!(s, "{}", compile_expr(item, linker, vars, func_args, strings, 32, sym).unwrap())?
+            }
+        }
+
+        Expr::FuncCall { name, args } => {
+            let arg_types = args
+                .iter()
+                .map(|arg| get_expr_type(arg, linker, vars, func_args, sym))
+                .collect::<Vec<_>>();
+            let mut wide = false;
+            if let Some(func) = linker.get_func(name, &arg_types) {
+                if *func.get_ret_type() == Type::Named(String::from("void")) {
+                    eprintln!("Error: Function {} returns void and cannot be used in an expression at line {}", name, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+                wide = is_two_words(func.get_ret_type())
+            }
+
+            write!(s, "{}", compile_call(name, args, linker, vars, func_args, strings, sym).unwrap())?;
+            if wide {
+                writeln!(s, "PSH R3")?
+            }
+            writeln!(s, "PSH R2\n")?
+        }
+    }
+
+    Ok(s)
+}
+
+// Compiles a value for a variable of type `typ`, zero filling array literals that are too short
+fn compile_init(
+    typ: &Type,
+    expr: &Expr,
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    if let (Type::Arr(elem_type, Some(len)), Expr::Array(items)) = (typ.unalias(), expr) {
+        for _ in 0..(len - items.len() as u64) * linker.size_of(elem_type) {
+            writeln!(s, "PSH 0")?
+        }
+        for item in items.iter().rev() {
+            write!(s, "{}", compile_init(elem_type, item, linker, vars, func_args, strings, sym).unwrap())?
+        }
+    } else {
+        write!(s, "{}", compile_converted(expr, typ, linker, vars, func_args, strings, sym).unwrap())?
+    }
+
+    Ok(s)
+}
+
+// Pushes the fields of a union variant followed by its tag, which ends up first in memory
+#[allow(clippy::too_many_arguments)]
+fn compile_union_value(
+    name: &String,
+    variant: &String,
+    args: &[Expr],
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    let typ = get_expr_type(
+        &Expr::UnionValue {
+            name: name.clone(),
+            variant: variant.clone(),
+            args: args.to_vec(),
+        },
+        linker,
+        vars,
+        func_args,
+        sym,
+    );
+    let union = linker.get_union(name).unwrap().clone();
+    let (tag, fields) = union.get_variant(variant).unwrap();
+
+    // Smaller variants leave the end of the union zeroed
+    let fields_size = fields.iter().map(|(typ, _)| linker.size_of(typ)).sum::<u64>();
+    for _ in fields_size + 1..linker.size_of(&typ) {
+        writeln!(s, "PSH 0")?
+    }
+    for (arg, (field_type, _)) in args.iter().zip(fields).rev() {
+        let arg_type = get_expr_type(arg, linker, vars, func_args, sym);
+        let convertible = match (field_type.unalias(), arg) {
+            (Type::Arr(..), Expr::Array(_)) => true,
+            _ => implicit_conversion(&arg_type, field_type).is_some(),
+        };
+        if !convertible {
+            eprintln!(
+                "Error: Cannot implicitly convert {} to {} in {}::{} at line {}, this needs a cast",
+                arg_type, field_type, name, variant, sym.lineno
+            );
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+        if let Some(warning) = narrowing_warning(arg, &arg_type, field_type) {
+            eprintln!("Warning: {} in {}::{} at line {}", warning, name, variant, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val)
+        }
+        write!(s, "{}", compile_init(field_type, arg, linker, vars, func_args, strings, sym).unwrap())?
+    }
+    writeln!(s, "PSH {}\n", tag)?;
+
+    Ok(s)
+}
+
+// Adds the initial value of a global to its words and returns the type with the array length filled in.
+// Only literals and addresses of other globals are constant, anything else would need code to run first
+fn compile_global_data(typ: &Type, expr: &Expr, global: &mut LinkerGlobal, linker: &Linker, sym: &DebugSym) -> Type {
+    match (typ.unalias(), expr) {
+        // The characters are copied into the array, which is zero filled after them
+        (Type::Arr(elem_type, len), Expr::Str(value)) if *elem_type.strip_const() == Type::Named(String::from("char")) => {
+            let chars = value.chars().count() as u64;
+            let len = len.unwrap_or(chars + 1);
+            if chars > len {
+                eprintln!("Error: String of length {} does not fit into {} at line {}", chars, typ, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            global.words.extend(value.chars().map(|c| (c as u32).to_string()));
+            global.words.extend((chars..len).map(|_| String::from("0")));
+            Type::Arr(elem_type.clone(), Some(len))
+        }
+
+        (Type::Arr(elem_type, len), Expr::Array(items)) => {
+            let len = len.unwrap_or(items.len() as u64);
+            if items.len() as u64 > len {
+                eprintln!("Error: Too many elements in initializer for {} at line {}", typ, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            for item in items {
+                compile_global_data(elem_type, item, global, linker, sym);
+            }
+            let padding = (len - items.len() as u64) * linker.size_of(elem_type);
+            global.words.extend((0..padding).map(|_| String::from("0")));
+            Type::Arr(elem_type.clone(), Some(len))
+        }
+
+        _ => {
+            check_assign(typ, expr, linker, &VarStack::new(), &None, sym);
+
+            // Same conversions as `compile_converted` does for literals
+            let int_words = |num: i64| {
+                let val = wrap_value(num, typ) as u64;
+                if is_wide(typ) {
+                    vec![(val & 0xffff_ffff).to_string(), (val >> 32).to_string()]
+                } else {
+                    vec![(val & 0xffff_ffff).to_string()]
+                }
+            };
+            let words = match expr {
+                Expr::Float(val) if is_float(typ) => Some(float_words(*val, typ)),
+                Expr::Float(val) => Some(int_words(*val as i64)),
+                Expr::Str(value) => {
+                    global.strings.push(value.clone());
+                    Some(vec![format!("{}_str{}", global.get_label(), global.strings.len() - 1)])
+                }
+                // `check_assign` already made sure that the variable exists, and only globals do here
+                Expr::AddrOf(inner) => match &**inner {
+                    Expr::Ident(name) => Some(vec![linker.get_global(name).unwrap().get_label()]),
+                    _ => None,
+                },
+                // Literals, other constants and arithmetic on them
+                _ => const_eval(expr, linker, &VarStack::new(), &None, sym).map(|num| {
+                    if is_float(typ) {
+                        float_words(num as f64, typ)
+                    } else {
+                        int_words(num)
+                    }
+                }),
+            };
+            let Some(words) = words else {
+                eprintln!("Error: Initializer of global {} is not a constant at line {}", global.name, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            };
+            global.words.extend(words);
+            if typ.is_const() && (typ.int_info().is_some() || is_bool(typ) || get_enum(typ, linker).is_some()) {
+                global.value = const_eval(expr, linker, &VarStack::new(), &None, sym).map(|num| wrap_value(num, typ))
+            }
+            typ.clone()
+        }
+    }
+}
+
+// Only numbers and pointers that are not constant can be updated in place
+fn check_update(typ: &Type, linker: &Linker, sym: &DebugSym) {
+    if typ.is_const() {
+        eprintln!("Error: Cannot assign to constant of type {} at line {}", typ, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+    if is_passed_by_pointer(typ, linker) || is_bool(typ) {
+        eprintln!("Error: Cannot update {} in place at line {}", typ, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+}
+
+// Computes `R2 op rhs` into R2, where R2 holds a value of type `typ` and `rhs` is a register or an immediate
+fn compile_update(typ: &Type, op: &Operation, rhs: &str, rhs_is_float: bool, linker: &Linker, sym: &DebugSym) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    if is_float(typ) || rhs_is_float {
+        let op_str = match op {
+            Operation::Add => "FADD",
+            Operation::Sub => "FSUB",
+            Operation::Mult => "FMLT",
+            Operation::Div => "FDIV",
+            _ => {
+                eprintln!("Error: Invalid operation {:?} on float types at line {}", op, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+        };
+        if !is_float(typ) {
+            writeln!(s, "ITOF R2 R2")?
+        }
+        if !rhs_is_float {
+            writeln!(s, "ITOF {} {}", rhs, rhs)?
+        }
+        writeln!(s, "{} R2 R2 {}", op_str, rhs)?;
+        if !is_float(typ) {
+            writeln!(s, "FTOI R2 R2")?;
+            write!(s, "{}", compile_wrap(typ, "R2").unwrap())?
+        }
+        return Ok(s);
+    }
+
+    match (op, typ.strip_const()) {
+        // Pointers move in steps of the pointed to type
+        (Operation::Add | Operation::Sub, Type::Ptr(elem_type)) => {
+            let size = linker.size_of(elem_type);
+            match rhs.parse::<u64>() {
+                Ok(num) => writeln!(s, "{} R2 R2 {}", get_op_str(op), num.wrapping_mul(size) & 0xffff_ffff)?,
+                Err(_) => {
+                    if size > 1 {
+                        writeln!(s, "MLT {} {} {}", rhs, rhs, size)?
+                    }
+                    writeln!(s, "{} R2 R2 {}", get_op_str(op), rhs)?
+                }
+            }
+        }
+        (_, Type::Ptr(_)) => {
+            eprintln!("Error: Invalid operation {:?} on pointer type {} at line {}", op, typ, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+        _ => {
+            write!(s, "{}", compile_int_op(op, is_signed(typ), rhs).unwrap())?;
+            write!(s, "{}", compile_wrap(typ, "R2").unwrap())?
+        }
+    }
+
+    Ok(s)
+}
+
+// Compiles `expr` and converts its value to `typ`
+fn compile_converted(
+    expr: &Expr,
+    typ: &Type,
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    match expr {
+        // Literals are converted at compile time
+        Expr::Number(num) if is_float(typ) => write!(s, "{}", compile_float_literal(*num as f64, typ).unwrap())?,
+        Expr::Number(num) if typ.int_info().is_some() => write!(s, "{}", compile_int_literal(*num, typ).unwrap())?,
+        Expr::Float(val) if is_float64(typ) => write!(s, "{}", compile_float_literal(*val, typ).unwrap())?,
+        Expr::Float(val) if !is_float(typ) => write!(s, "{}", compile_int_literal(*val as i64, typ).unwrap())?,
+
+        _ => {
+            write!(s, "{}", compile_expr(expr, linker, vars, func_args, strings, 32, sym).unwrap())?;
+            let expr_type = promote_enum(&get_expr_type(expr, linker, vars, func_args, sym), linker);
+
+            if is_bool(typ) && !is_bool(&expr_type) {
+                write!(s, "{}", compile_pop_truth(&expr_type).unwrap())?;
+                writeln!(s, "SETNE R2 R2 0")?;
+                writeln!(s, "AND R2 R2 1")?;
+                writeln!(s, "PSH R2\n")?
+            } else if is_float(typ) || is_float(&expr_type) {
+                write!(s, "{}", compile_float_conversion(&expr_type, typ, linker).unwrap())?
+            } else if (expr_type.int_info().is_some() || typ.int_info().is_some()) && expr_type.strip_const() != typ.strip_const() {
+                match (is_wide(&expr_type), is_wide(typ)) {
+                    // The high word is filled with the sign, or zeros for unsigned values
+                    (false, true) => {
+                        writeln!(s, "POP R2")?;
+                        if is_signed(&expr_type) {
+                            writeln!(s, "BSS R3 R2 31")?
+                        } else {
+                            writeln!(s, "IMM R3 0")?
+                        }
+                        writeln!(s, "PSH R3")?;
+                        writeln!(s, "PSH R2\n")?
+                    }
+                    // Only the low word is kept
+                    (true, false) => {
+                        writeln!(s, "POP R2")?;
+                        writeln!(s, "POP R3")?;
+                        write!(s, "{}", compile_wrap(typ, "R2").unwrap())?;
+                        writeln!(s, "PSH R2\n")?
+                    }
+                    (true, true) => {}
+                    (false, false) => {
+                        let wrap = compile_wrap(typ, "R2").unwrap();
+                        if !wrap.is_empty() {
+                            writeln!(s, "POP R2")?;
+                            write!(s, "{}", wrap)?;
+                            writeln!(s, "PSH R2\n")?
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(s)
+}
+
+// Converts the value of type `from` on top of the stack to `to`, at least one of them being a float.
+// Conversions that involve a float64 or a 64 bit integer go through the runtime, float32s and 64 bit integers
+// are converted through a float64.
+fn compile_float_conversion(from: &Type, to: &Type, linker: &mut Linker) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    let float64 = Type::Named(String::from("float64"));
+
+    match (is_float(from), is_float(to)) {
+        (true, true) if is_float64(from) == is_float64(to) => {}
+        (true, true) => {
+            if is_float64(to) {
+                write!(s, "{}", compile_runtime_call("rt_f32tof64", 1, linker).unwrap())?;
+                writeln!(s, "PSH R3")?
+            } else {
+                write!(s, "{}", compile_runtime_call("rt_f64tof32", 2, linker).unwrap())?
+            }
+            writeln!(s, "PSH R2\n")?
+        }
+        // Integers are widened to 64 bits first, like for any other two word operation
+        (false, true) if is_float64(to) || is_wide(from) => {
+            if !is_wide(from) {
+                writeln!(s, "POP R2")?;
+                if is_signed(from) {
+                    writeln!(s, "BSS R3 R2 31")?
+                } else {
+                    writeln!(s, "IMM R3 0")?
+                }
+                writeln!(s, "PSH R3")?;
+                writeln!(s, "PSH R2")?
+            }
+            let routine = if is_wide(from) && !is_signed(from) {
+                "rt_u64tof64"
+            } else {
+                "rt_i64tof64"
+            };
+            write!(s, "{}", compile_runtime_call(routine, 2, linker).unwrap())?;
+            writeln!(s, "PSH R3")?;
+            writeln!(s, "PSH R2\n")?;
+            write!(s, "{}", compile_float_conversion(&float64, to, linker).unwrap())?
+        }
+        (false, true) => {
+            writeln!(s, "POP R2")?;
+            writeln!(s, "ITOF R2 R2")?;
+            writeln!(s, "PSH R2\n")?
+        }
+        (true, false) if is_float64(from) || is_wide(to) => {
+            write!(s, "{}", compile_float_conversion(from, &float64, linker).unwrap())?;
+            write!(s, "{}", compile_runtime_call("rt_f64toi64", 2, linker).unwrap())?;
+            if is_wide(to) {
+                writeln!(s, "PSH R3")?
+            } else {
+                write!(s, "{}", compile_wrap(to, "R2").unwrap())?
+            }
+            writeln!(s, "PSH R2\n")?
+        }
+        (true, false) => {
+            writeln!(s, "POP R2")?;
+            writeln!(s, "FTOI R2 R2")?;
+            write!(s, "{}", compile_wrap(to, "R2").unwrap())?;
+            writeln!(s, "PSH R2\n")?
+        }
+        (false, false) => unreachable!("{} and {} are not floats", from, to),
+    }
+
+    Ok(s)
+}
+
+// Leaves the operands of a comparison in R2 and R3, returns whether they have to be compared as signed
+fn compile_comp_operands(
+    lhs: &Expr,
+    rhs: &Expr,
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Result<(String, bool), std::fmt::Error> {
+    let mut s = String::new();
+
+    // Enums can only be compared to values of the same enum, structs and unions not at all
+    let lhs_type = get_expr_type(lhs, linker, vars, func_args, sym);
+    let rhs_type = get_expr_type(rhs, linker, vars, func_args, sym);
+    let mismatched_enums =
+        (get_enum(&lhs_type, linker).is_some() || get_enum(&rhs_type, linker).is_some()) && lhs_type.strip_const() != rhs_type.strip_const();
+    if mismatched_enums || is_struct(&lhs_type, linker) || is_struct(&rhs_type, linker) {
+        eprintln!("Error: Cannot compare {} with {} at line {}", lhs_type, rhs_type, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+
+    let lhs_type = promote_enum(&operand_type(lhs, linker, vars, func_args, sym), linker);
+    let rhs_type = promote_enum(&operand_type(rhs, linker, vars, func_args, sym), linker);
+    let is_float_comp = is_float(&lhs_type) || is_float(&rhs_type);
+    let typ = if is_float_comp {
+        common_float_type(&lhs_type, &rhs_type)
+    } else {
+        common_int_type(&lhs_type, &rhs_type)
+    };
+
+    // Two word values are compared by their high words, or their low words when those are equal.
+    // That leaves -1, 0 or 1 in R2 to be compared against 0 instead.
+    if is_two_words(&typ) {
+        write!(s, "{}", compile_converted(lhs, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+        write!(s, "{}", compile_converted(rhs, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+        writeln!(s, "POP R3")?;
+        writeln!(s, "POP R5")?;
+        writeln!(s, "POP R2")?;
+        writeln!(s, "POP R4")?;
+
+        // Same as for float32s below, negating the low word carries into the high word when it is 0
+        if is_float_comp {
+            for (low, high) in [("R2", "R4"), ("R3", "R5")] {
+                writeln!(s, "BSS R6 {} 31", high)?;
+                writeln!(s, "AND {} {} 0x7fffffff", high, high)?;
+                writeln!(s, "XOR {} {} R6", low, low)?;
+                writeln!(s, "XOR {} {} R6", high, high)?;
+                writeln!(s, "SUB {} {} R6", low, low)?;
+                writeln!(s, "SETE R7 {} 0", low)?;
+                writeln!(s, "AND R7 R7 R6")?;
+                writeln!(s, "SUB {} {} R7", high, high)?
+            }
+        }
+
+        let signed = is_float_comp || is_signed(&typ);
+        let id = new_label_id();
+        writeln!(s, "BNE .cmp{}_high R4 R5", id)?;
+        writeln!(s, "SETG R6 R2 R3")?;
+        writeln!(s, "SETL R7 R2 R3")?;
+        writeln!(s, "JMP .cmp{}_end", id)?;
+        writeln!(s, ".cmp{}_high", id)?;
+        writeln!(s, "{} R6 R4 R5", get_comp_set_str(&Comparison::GT, signed))?;
+        writeln!(s, "{} R7 R4 R5", get_comp_set_str(&Comparison::LT, signed))?;
+        writeln!(s, ".cmp{}_end", id)?;
+        writeln!(s, "SUB R2 R7 R6")?;
+        writeln!(s, "IMM R3 0")?;
+        return Ok((s, true));
+    }
+
+    if is_float_comp {
+        write!(s, "{}", compile_converted(lhs, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+        write!(s, "{}", compile_converted(rhs, &typ, linker, vars, func_args, strings, sym).unwrap())?;
+    } else {
+        write!(s, "{}", compile_expr(lhs, linker, vars, func_args, strings, 32, sym).unwrap())?;
+        write!(s, "{}", compile_expr(rhs, linker, vars, func_args, strings, 32, sym).unwrap())?;
+    }
+    writeln!(s, "POP R3")?;
+    writeln!(s, "POP R2")?;
+
+    // Integers are compared after being brought to a common type
+    if !is_float_comp {
+        if lhs_type != typ {
+            write!(s, "{}", compile_wrap(&typ, "R2").unwrap())?
+        }
+        if rhs_type != typ {
+            write!(s, "{}", compile_wrap(&typ, "R3").unwrap())?
+        }
+    }
+
+    // Floats are sign and magnitude, turning them into two's complement makes them order like signed integers
+    if is_float_comp {
+        for reg in ["R2", "R3"] {
+            writeln!(s, "BSS R4 {} 31", reg)?;
+            writeln!(s, "AND {} {} 0x7fffffff", reg, reg)?;
+            writeln!(s, "XOR {} {} R4", reg, reg)?;
+            writeln!(s, "SUB {} {} R4", reg, reg)?
+        }
+    }
+
+    Ok((s, is_float_comp || is_signed(&typ)))
+}
+
+// Emits code that jumps to `false_label` when `cond` does not hold and falls through otherwise
+fn compile_cond(
+    cond: &Expr,
+    false_label: &str,
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    match cond {
+        Expr::Comp { lhs, comp, rhs } => {
+            let (operands, signed) = compile_comp_operands(lhs, rhs, linker, vars, func_args, strings, sym)?;
+            write!(s, "{}", operands)?;
+            writeln!(s, "{} {} R2 R3", get_inv_branch_str(comp, signed), false_label)?
+        }
+
+        Expr::Number(num) => {
+            if *num == 0 {
+                writeln!(s, "JMP {}", false_label)?
+            }
+        }
+
+        Expr::Bool(val) => {
+            if !val {
+                writeln!(s, "JMP {}", false_label)?
+            }
+        }
+
+        Expr::Logic { lhs, op: Logic::And, rhs } => {
+            write!(s, "{}", compile_cond(lhs, false_label, linker, vars, func_args, strings, sym).unwrap())?;
+            write!(s, "{}", compile_cond(rhs, false_label, linker, vars, func_args, strings, sym).unwrap())?
+        }
+
+        Expr::Logic { lhs, op: Logic::Or, rhs } => {
+            let id = new_label_id();
+            let rhs_label = format!(".logic{}_rhs", id);
+            write!(s, "{}", compile_cond(lhs, &rhs_label, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, "JMP .logic{}_true", id)?;
+            writeln!(s, "{}", rhs_label)?;
+            write!(s, "{}", compile_cond(rhs, false_label, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, ".logic{}_true", id)?
+        }
+
+        // Jumps to `false_label` exactly when the inner condition holds
+        Expr::Not(inner) => {
+            let id = new_label_id();
+            let true_label = format!(".logic{}_true", id);
+            write!(s, "{}", compile_cond(inner, &true_label, linker, vars, func_args, strings, sym).unwrap())?;
+            writeln!(s, "JMP {}", false_label)?;
+            writeln!(s, "{}", true_label)?
+        }
+
+        _ => {
+            check_condition(&get_expr_type(cond, linker, vars, func_args, sym), sym);
+            write!(s, "{}", compile_expr(cond, linker, vars, func_args, strings, 32, sym).unwrap())?;
+            write!(s, "{}", compile_pop_truth(&get_expr_type(cond, linker, vars, func_args, sym)).unwrap())?;
+            writeln!(s, "BRZ {} R2", false_label)?
+        }
+    }
+
+    Ok(s)
+}
+
+// Pushes the arguments right to left and calls the function, leaving the return value in R2
+fn compile_call(
+    name: &String,
+    args: &[Expr],
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    let arg_types = args
+        .iter()
+        .map(|arg| get_expr_type(arg, linker, vars, func_args, sym))
+        .collect::<Vec<_>>();
+    let Some(func) = linker.get_func(name, &arg_types) else {
+        eprintln!(
+            "Error: Undefined function {}({}) at line {}",
+            name,
+            arg_types.iter().map(|typ| typ.to_string()).collect::<Vec<_>>().join(", "),
+            sym.lineno
+        );
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    };
+
+    for (arg, arg_type) in args.iter().zip(func.get_arg_types()).rev() {
+        let value_type = get_expr_type(arg, linker, vars, func_args, sym);
+        if is_passed_by_pointer(&value_type, linker) {
+            // The callee works on the caller's value, so it must not lose its constness like a copy could
+            if value_type.is_const() && !arg_type.is_const() {
+                eprintln!(
+                    "Error: Passing {} as {} discards const in call to {} at line {}",
+                    value_type, arg_type, name, sym.lineno
+                );
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            let location = get_location(arg, linker, vars, func_args, strings, sym);
+            write!(s, "{}", location.code)?;
+            writeln!(s, "ADD R2 {} {}", location.base, location.offset)?;
+            writeln!(s, "PSH R2")?
+        } else {
+            if let Some(warning) = narrowing_warning(arg, &value_type, arg_type) {
+                eprintln!("Warning: {} in call to {} at line {}", warning, name, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val)
+            }
+            write!(s, "{}", compile_converted(arg, arg_type, linker, vars, func_args, strings, sym).unwrap())?
+        }
+    }
+    writeln!(s, "CAL .{}", func.get_signature())?;
+    let arg_words = func
+        .get_arg_types()
+        .iter()
+        .map(|typ| if is_passed_by_pointer(typ, linker) { 1 } else { linker.size_of(typ) })
+        .sum::<u64>();
+    if arg_words > 0 {
+        writeln!(s, "ADD SP SP {}", arg_words)?
+    }
+
+    Ok(s)
+}
+
+fn get_expr_type(expr: &Expr, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) -> Type {
+    match expr {
+        // Untyped integer, matches any integer type
+        Expr::Number(_) => Type::Named(String::from("int")),
+
+        Expr::Bool(_) | Expr::Comp { .. } | Expr::Logic { .. } | Expr::Not(_) => Type::Named(String::from("bool")),
+
+        Expr::Float(_) => Type::Named(String::from("float32")),
+        Expr::Char(_) => Type::Named(String::from("char")),
+
+        Expr::Str(_) => Type::Named(String::from("string")),
+
+        Expr::EnumValue { name, variant } if linker.get_union(name).is_some() => get_expr_type(
+            &Expr::UnionValue {
+                name: name.clone(),
+                variant: variant.clone(),
+                args: vec![],
+            },
+            linker,
+            vars,
+            func_args,
+            sym,
+        ),
+
+        Expr::UnionValue { name, variant, args } => {
+            let Some(union) = linker.get_union(name) else {
+                eprintln!("Error: Unknown union {} at line {}", name, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            };
+            let Some((_, fields)) = union.get_variant(variant) else {
+                eprintln!("Error: Union {} has no variant {} at line {}", name, variant, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            };
+            if fields.len() != args.len() {
+                eprintln!(
+                    "Error: Variant {}::{} takes {} values, but got {} at line {}",
+                    name,
+                    variant,
+                    fields.len(),
+                    args.len(),
+                    sym.lineno
+                );
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            Type::Named(name.clone())
+        }
+
+        Expr::EnumValue { name, variant } => {
+            let Some(enum_type) = linker.get_enum(name) else {
+                eprintln!("Error: Unknown enum {} at line {}", name, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            };
+            if enum_type.get_value(variant).is_none() {
+                eprintln!("Error: Enum {} has no variant {} at line {}", name, variant, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            Type::Named(name.clone())
+        }
+
+        Expr::Ident(name) => {
+            if let Some(typ) = vars.get_type(name) {
+                return typ;
+            }
+            if let Some(func_args) = func_args {
+                if let Some(typ) = func_args.get_type(name) {
+                    return typ;
+                }
+            }
+            if let Some(global) = linker.get_global(name) {
+                return global.typ.clone();
+            }
+
+            eprintln!("Error: Undefined variable {} at line {}", name, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+
+        Expr::BiOp { lhs, op, rhs } => {
+            // The result is a new value, so it is never constant itself
+            let lhs_type = operand_type(lhs, linker, vars, func_args, sym);
+            let rhs_type = operand_type(rhs, linker, vars, func_args, sym);
+            match (&lhs_type, &rhs_type, op) {
+                // Difference between two pointers is an element count
+                (Type::Ptr(_), Type::Ptr(_), Operation::Sub) => Type::Named(String::from("int")),
+                (Type::Ptr(_), _, _) => lhs_type,
+                (_, Type::Ptr(_), _) => rhs_type,
+                // Mixing integers and floats gives a float
+                _ if is_float(&lhs_type) || is_float(&rhs_type) => common_float_type(&lhs_type, &rhs_type),
+                // Shifts keep the type of the shifted value
+                (_, _, Operation::Shl | Operation::Shr) => lhs_type,
+                _ => common_int_type(&lhs_type, &rhs_type),
+            }
+        }
+
+        // Sizes are counted in words and behave like integer literals
+        Expr::SizeOf(_) | Expr::SizeOfType(_) | Expr::AlignOf(_) => Type::Named(String::from("int")),
+
+        Expr::Ternary { then, otherwise, .. } => {
+            let then_type = get_expr_type(then, linker, vars, func_args, sym).strip_const().clone();
+            let otherwise_type = get_expr_type(otherwise, linker, vars, func_args, sym).strip_const().clone();
+            let is_number = |typ: &Type| typ.int_info().is_some() || is_float(typ) || is_bool(typ);
+            match (&then_type, &otherwise_type) {
+                _ if then_type == otherwise_type => then_type,
+                // Numbers meet like the operands of arithmetic
+                _ if is_number(&then_type) && is_number(&otherwise_type) => {
+                    let (then_type, otherwise_type) = (promote_bool(&then_type), promote_bool(&otherwise_type));
+                    if is_float(&then_type) || is_float(&otherwise_type) {
+                        common_float_type(&then_type, &otherwise_type)
+                    } else {
+                        common_int_type(&then_type, &otherwise_type)
+                    }
+                }
+                _ if implicit_conversion(&otherwise_type, &then_type).is_some() => then_type,
+                _ if implicit_conversion(&then_type, &otherwise_type).is_some() => otherwise_type,
+                _ => {
+                    eprintln!(
+                        "Error: Mismatched types {} and {} in conditional expression at line {}",
+                        then_type, otherwise_type, sym.lineno
+                    );
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+            }
+        }
+
+        Expr::FuncCall { name, args } => {
+            let arg_types = args
+                .iter()
+                .map(|arg| get_expr_type(arg, linker, vars, func_args, sym))
+                .collect::<Vec<_>>();
+            match linker.get_func(name, &arg_types) {
+                Some(func) => func.get_ret_type().clone(),
+                None => {
+                    eprintln!("Error: Undefined function {} at line {}", name, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+            }
+        }
+
+        Expr::Member { expr, field } => {
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            let field_type = get_field(&typ, field, linker, sym).0;
+            // Fields of a constant struct are constant too
+            if typ.is_const() {
+                make_const(field_type)
+            } else {
+                field_type
+            }
+        }
+
+        Expr::Index { expr, .. } => {
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            match typ.strip_const() {
+                // Elements of a constant array are constant too
+                Type::Arr(elem_type, _) if typ.is_const() => make_const((**elem_type).clone()),
+                Type::Arr(elem_type, _) | Type::Ptr(elem_type) => (**elem_type).clone(),
+                _ => {
+                    eprintln!("Error: Cannot index into {} at line {}", typ, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+            }
+        }
+
+        Expr::AddrOf(expr) => Type::Ptr(Box::new(get_expr_type(expr, linker, vars, func_args, sym))),
+
+        Expr::Cast { typ, .. } => resolve_type(typ, linker, vars, func_args, sym),
+
+        Expr::Neg(expr) | Expr::BitNot(expr) => operand_type(expr, linker, vars, func_args, sym),
+        Expr::IncDec { expr, .. } => get_expr_type(expr, linker, vars, func_args, sym).strip_const().clone(),
+
+        Expr::Deref(expr) => match get_expr_type(expr, linker, vars, func_args, sym).strip_const() {
+            Type::Ptr(typ) => (**typ).clone(),
+            typ => {
+                eprintln!("Error: Cannot dereference non-pointer type {} at line {}", typ, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+        },
+
+        Expr::Array(items) => Type::Arr(
+            Box::new(match items.first() {
+                Some(item) => get_expr_type(item, linker, vars, func_args, sym),
+                None => Type::Named(String::from("int")),
+            }),
+            Some(items.len() as u64),
+        ),
+    }
+}
+
+fn get_field(typ: &Type, field: &String, linker: &Linker, sym: &DebugSym) -> (Type, u64) {
+    if let Type::Named(name) = typ.strip_const() {
+        if linker.get_struct(name).is_none() {
+            eprintln!("Error: Type {} is not a struct at line {}", typ, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+        if let Some(field) = linker.get_field(name, field) {
+            return field;
+        }
+    }
+
+    eprintln!("Error: Type {} has no field {} at line {}", typ, field, sym.lineno);
+    eprintln!("{}: {}", sym.lineno, sym.val);
+    exit(1)
+}
+
+// Unions count too, as they are laid out and passed the same way
+fn is_struct(typ: &Type, linker: &Linker) -> bool {
+    matches!(typ.strip_const(), Type::Named(name) if linker.get_struct(name).is_some() || linker.get_union(name).is_some())
+}
+
+fn is_passed_by_pointer(typ: &Type, linker: &Linker) -> bool {
+    is_struct(typ, linker) || matches!(typ.strip_const(), Type::Arr(..))
+}
+
+// Type an operand of arithmetic is computed in, bools count as int32s and untyped literals that need more than 32 bits as int64s
+fn operand_type(expr: &Expr, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) -> Type {
+    match expr {
+        Expr::Number(num) if is_wide_literal(*num) => Type::Named(String::from("int64")),
+        _ => promote_bool(get_expr_type(expr, linker, vars, func_args, sym).strip_const()),
+    }
+}
+
+// Untyped integer literals count as signed, like C's int
+fn is_signed(typ: &Type) -> bool {
+    typ.int_info().is_some_and(|(_, signed)| signed)
+}
+
+// The wider of two integer types, the unsigned one if they are equally wide, untyped literals take the type of the other side
+fn common_int_type(lhs: &Type, rhs: &Type) -> Type {
+    let literal = Type::Named(String::from("int"));
+    match (lhs.int_info(), rhs.int_info()) {
+        _ if *lhs == literal => rhs.clone(),
+        _ if *rhs == literal => lhs.clone(),
+        (Some((lhs_width, lhs_signed)), Some((rhs_width, rhs_signed)))
+            if rhs_width > lhs_width || (rhs_width == lhs_width && lhs_signed && !rhs_signed) =>
+        {
+            rhs.clone()
+        }
+        _ => lhs.clone(),
+    }
+}
+
+// 64 bit integers take up two words
+fn is_wide(typ: &Type) -> bool {
+    typ.int_info().is_some_and(|(width, _)| width == 64)
+}
+
+// Like 64 bit integers, these are returned in R2 and R3
+fn is_two_words(typ: &Type) -> bool {
+    is_wide(typ) || is_float64(typ)
+}
+
+// Value of `num` after being stored into an integer of type `typ`
+fn wrap_value(num: i64, typ: &Type) -> i64 {
+    match typ.int_info() {
+        Some((64, _)) | None => num,
+        Some((width, true)) => num << (64 - width) >> (64 - width),
+        Some((width, false)) => num & ((1 << width) - 1),
+    }
+}
+
+// Pushes `num` as a value of the integer type `typ`
+fn compile_int_literal(num: i64, typ: &Type) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    let val = wrap_value(num, typ) as u64;
+    if is_wide(typ) {
+        writeln!(s, "PSH {}", val >> 32)?
+    }
+    writeln!(s, "PSH {}\n", val & 0xffff_ffff)?;
+    Ok(s)
+}
+
+// Words of a float literal of type `typ`, low word first
+fn float_words(val: f64, typ: &Type) -> Vec<String> {
+    if is_float64(typ) {
+        let bits = val.to_bits();
+        vec![format!("0x{:x}", bits & 0xffff_ffff), format!("0x{:x}", bits >> 32)]
+    } else {
+        vec![format!("0x{:x}", (val as f32).to_bits())]
+    }
+}
+
+// Pushes `val` as a value of the float type `typ`
+fn compile_float_literal(val: f64, typ: &Type) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    for word in float_words(val, typ).iter().rev() {
+        writeln!(s, "PSH {}", word)?
+    }
+    writeln!(s)?;
+    Ok(s)
+}
+
+// Pops a value of type `typ` into R2, where it is non zero exactly when the value is
+fn compile_pop_truth(typ: &Type) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    writeln!(s, "POP R2")?;
+    if is_wide(typ) {
+        writeln!(s, "POP R3")?;
+        writeln!(s, "OR R2 R2 R3")?
+    }
+    Ok(s)
+}
+
+// Brings the value in `reg` into the range of `typ`, narrow signed values are kept sign extended to the full word
+fn compile_wrap(typ: &Type, reg: &str) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    match typ.int_info() {
+        Some((width, true)) if width < 32 => {
+            writeln!(s, "BSL {} {} {}", reg, reg, 32 - width)?;
+            writeln!(s, "BSS {} {} {}", reg, reg, 32 - width)?
+        }
+        Some((width, false)) if width < 32 => writeln!(s, "AND {} {} 0x{:x}", reg, reg, (1u32 << width) - 1)?,
+        _ => {}
+    }
+    Ok(s)
+}
+
+// Describes what converting `expr` to `typ` without a cast loses, if anything
+fn narrowing_warning(expr: &Expr, expr_type: &Type, typ: &Type) -> Option<String> {
+    if is_float64(expr_type) && is_float(typ) && !is_float64(typ) {
+        return Some(format!("Implicit conversion from {} to {} may lose precision", expr_type, typ));
+    }
+    let (width, signed) = typ.int_info()?;
+    match expr {
+        Expr::Number(_) if width == 64 => None,
+        // Negative literals are fine for unsigned types, they wrap around like in C
+        Expr::Number(num) => {
+            let max = if signed { (1i64 << (width - 1)) - 1 } else { (1i64 << width) - 1 };
+            if *num < -(1i64 << (width - 1)) || *num > max {
+                Some(format!("Implicit conversion of {} to {} changes its value to {}", num, typ, wrap_value(*num, typ)))
+            } else {
+                None
+            }
+        }
+        _ if is_float(expr_type) => Some(format!("Implicit conversion from {} to {} drops the fractional part", expr_type, typ)),
+        // Untyped values are as wide as they need to be
+        _ if *expr_type.strip_const() == Type::Named(String::from("int")) => None,
+        _ => match expr_type.int_info() {
+            Some((expr_width, _)) if expr_width > width => Some(format!("Implicit conversion from {} to {} may lose data", expr_type, typ)),
+            _ => None,
+        },
+    }
+}
+
+fn is_bool(typ: &Type) -> bool {
+    *typ.strip_const() == Type::Named(String::from("bool"))
+}
+
+// Operators work on bools as if they were int32s
+fn promote_bool(typ: &Type) -> Type {
+    if is_bool(typ) {
+        Type::Named(String::from("int32"))
+    } else {
+        typ.clone()
+    }
+}
+
+fn get_enum<'a>(typ: &Type, linker: &'a Linker) -> Option<&'a LinkerEnum> {
+    match typ.strip_const() {
+        Type::Named(name) => linker.get_enum(name),
+        _ => None,
+    }
+}
+
+fn get_union<'a>(typ: &Type, linker: &'a Linker) -> Option<&'a LinkerUnion> {
+    match typ.strip_const() {
+        Type::Named(name) => linker.get_union(name),
+        _ => None,
+    }
+}
+
+// Enum values are int32s once they are converted or compared
+fn promote_enum(typ: &Type, linker: &Linker) -> Type {
+    if get_enum(typ, linker).is_some() {
+        Type::Named(String::from("int32"))
+    } else {
+        typ.clone()
+    }
+}
+
+// Value of a case label the way the scrutinee of type `typ` holds it
+fn get_case_value(
+    value: &Expr,
+    typ: &Type,
+    enum_type: &Option<LinkerEnum>,
+    linker: &Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    sym: &DebugSym,
+) -> i64 {
+    let num = match (value, enum_type) {
+        (Expr::EnumValue { name, variant }, Some(enum_type)) if *name == enum_type.name => enum_type.get_value(variant),
+        (Expr::Bool(val), None) if is_bool(typ) => Some(*val as i64),
+        // Constants and arithmetic on them, as long as they are no enum values meant for another switch
+        (_, None) if get_enum(&get_expr_type(value, linker, vars, func_args, sym), linker).is_none() => {
+            const_eval(value, linker, vars, func_args, sym).map(|num| wrap_value(num, typ))
+        }
+        _ => None,
+    };
+    let Some(num) = num else {
+        eprintln!("Error: Case is not a constant of type {} at line {}", typ, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    };
+    num
+}
+
+// Value of an integer expression that can be worked out at compile time, `None` if it needs code to run
+fn const_eval(expr: &Expr, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) -> Option<i64> {
+    let eval = |expr: &Expr| const_eval(expr, linker, vars, func_args, sym);
+    match expr {
+        Expr::Number(num) => Some(*num),
+        Expr::Char(c) => Some(*c as i64),
+        Expr::Bool(val) => Some(*val as i64),
+        Expr::Ident(name) if !is_local(name, vars, func_args) => linker.get_global(name)?.value,
+        Expr::EnumValue { name, variant } => linker.get_enum(name)?.get_value(variant),
+
+        Expr::SizeOf(inner) => Some(linker.size_of(&get_sizeof_type(inner, linker, vars, func_args, sym)) as i64),
+        Expr::SizeOfType(typ) => match sizeof_operand(typ, linker, vars, func_args) {
+            Some(operand) => Some(linker.size_of(&get_expr_type(&operand, linker, vars, func_args, sym)) as i64),
+            None => Some(linker.size_of(&resolve_type(typ, linker, vars, func_args, sym)) as i64),
+        },
+        // Memory is addressed in words, so nothing needs more than word alignment
+        Expr::AlignOf(typ) => {
+            linker.size_of(&resolve_type(typ, linker, vars, func_args, sym));
+            Some(1)
+        }
+
+        // Operands and results wrap around like in the type the generated code computes in
+        Expr::BiOp {
+            lhs: lhs_expr,
+            op,
+            rhs: rhs_expr,
+        } => {
+            let (lhs, rhs) = (eval(lhs_expr)?, eval(rhs_expr)?);
+            let typ = get_expr_type(expr, linker, vars, func_args, sym);
+            let (_, signed) = typ.int_info()?;
+            let lhs = wrap_value(lhs, &typ);
+            let rhs = if let Operation::Shl | Operation::Shr = op {
+                rhs
+            } else {
+                wrap_value(rhs, &typ)
+            };
+            let res = match op {
+                Operation::Add => lhs.wrapping_add(rhs),
+                Operation::Sub => lhs.wrapping_sub(rhs),
+                Operation::Mult => lhs.wrapping_mul(rhs),
+                Operation::Div if signed => lhs.checked_div(rhs)?,
+                Operation::Div => (lhs as u64).checked_div(rhs as u64)? as i64,
+                Operation::Mod if signed => lhs.checked_rem(rhs)?,
+                Operation::Mod => (lhs as u64).checked_rem(rhs as u64)? as i64,
+                Operation::BitAnd => lhs & rhs,
+                Operation::BitOr => lhs | rhs,
+                Operation::BitXor => lhs ^ rhs,
+                Operation::Shl => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                Operation::Shr if signed => lhs >> rhs.clamp(0, 63),
+                Operation::Shr => (lhs as u64).checked_shr(rhs as u32).unwrap_or(0) as i64,
+            };
+            Some(wrap_value(res, &typ))
+        }
+        Expr::Comp {
+            lhs: lhs_expr,
+            comp,
+            rhs: rhs_expr,
+        } => {
+            let (lhs, rhs) = (eval(lhs_expr)?, eval(rhs_expr)?);
+            let typ = common_int_type(
+                &promote_enum(&operand_type(lhs_expr, linker, vars, func_args, sym), linker),
+                &promote_enum(&operand_type(rhs_expr, linker, vars, func_args, sym), linker),
+            );
+            let (lhs, rhs) = (wrap_value(lhs, &typ), wrap_value(rhs, &typ));
+            // Unsigned 64 bit values above the signed range are compared by their bits
+            let ord = if is_signed(&typ) {
+                lhs.cmp(&rhs)
+            } else {
+                (lhs as u64).cmp(&(rhs as u64))
+            };
+            let res = match comp {
+                Comparison::EQ => ord.is_eq(),
+                Comparison::NEQ => ord.is_ne(),
+                Comparison::LT => ord.is_lt(),
+                Comparison::LTE => ord.is_le(),
+                Comparison::GT => ord.is_gt(),
+                Comparison::GTE => ord.is_ge(),
+            };
+            Some(res as i64)
+        }
+        Expr::Logic { lhs, op, rhs } => {
+            let (lhs, rhs) = (eval(lhs)? != 0, eval(rhs)? != 0);
+            match op {
+                Logic::And => Some((lhs && rhs) as i64),
+                Logic::Or => Some((lhs || rhs) as i64),
+            }
+        }
+        Expr::Not(inner) => Some((eval(inner)? == 0) as i64),
+        Expr::Neg(inner) => Some(wrap_value(eval(inner)?.wrapping_neg(), &get_expr_type(expr, linker, vars, func_args, sym))),
+        Expr::BitNot(inner) => Some(wrap_value(!eval(inner)?, &get_expr_type(expr, linker, vars, func_args, sym))),
+        Expr::Ternary { cond, then, otherwise } => {
+            if eval(cond)? != 0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+
+        Expr::Cast { expr: inner, typ } => {
+            let typ = resolve_type(typ, linker, vars, func_args, sym);
+            let num = eval(inner)?;
+            if is_bool(&typ) {
+                Some((num != 0) as i64)
+            } else if typ.int_info().is_some() || get_enum(&typ, linker).is_some() {
+                Some(wrap_value(num, &typ))
+            } else {
+                None
+            }
+        }
+
+        _ => None,
+    }
+}
+
+fn is_local(name: &String, vars: &VarStack, func_args: &Option<VarStack>) -> bool {
+    vars.get_offset(name).is_some() || func_args.as_ref().is_some_and(|args| args.get_offset(name).is_some())
+}
+
+// `sizeof(Point)` is parsed as an expression, so a name that is no variable is taken as a type
+fn get_sizeof_type(expr: &Expr, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) -> Type {
+    match expr {
+        Expr::Ident(name) if !is_local(name, vars, func_args) && linker.get_global(name).is_none() => {
+            resolve_type(&Type::Named(name.clone()), linker, vars, func_args, sym)
+        }
+        _ => get_expr_type(expr, linker, vars, func_args, sym),
+    }
+}
+
+// `sizeof(xs[2])` is parsed like a type, which is an index into `xs` again when `xs` is a variable
+fn sizeof_operand(typ: &Type, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>) -> Option<Expr> {
+    let mut indices = vec![];
+    let mut typ = typ;
+    loop {
+        match typ {
+            Type::Arr(inner, Some(len)) => {
+                indices.push(Expr::Number(*len as i64));
+                typ = inner
+            }
+            Type::ArrExpr(inner, len, _) => {
+                indices.push((**len).clone());
+                typ = inner
+            }
+            Type::Named(name) if is_local(name, vars, func_args) || linker.get_global(name).is_some() => {
+                // The outermost length is the first index
+                return Some(indices.into_iter().fold(Expr::Ident(name.clone()), |expr, index| Expr::Index {
+                    expr: Box::new(expr),
+                    index: Box::new(index),
+                }));
+            }
+            _ => return None,
+        }
+    }
+}
+
+// Replaces alias names in a type written in the source with the types they stand for and evaluates array lengths
+fn resolve_type(typ: &Type, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) -> Type {
+    let resolve = |typ: &Type| Box::new(resolve_type(typ, linker, vars, func_args, sym));
+    match typ {
+        Type::Named(name) => match linker.get_alias(name) {
+            Some(alias) => Type::Alias(name.clone(), Box::new(alias.typ.clone())),
+            None => typ.clone(),
+        },
+        Type::Ptr(typ) => Type::Ptr(resolve(typ)),
+        Type::Arr(typ, len) => Type::Arr(resolve(typ), *len),
+        Type::ArrExpr(typ, len, len_str) => match const_eval(len, linker, vars, func_args, sym) {
+            Some(len) if len >= 0 => Type::Arr(resolve(typ), Some(len as u64)),
+            Some(len) => {
+                eprintln!("Error: Array length {} is negative at line {}", len, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            None => {
+                eprintln!("Error: Array length {} is not a compile-time constant at line {}", len_str, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+        },
+        Type::Const(typ) => Type::Const(resolve(typ)),
+        Type::Alias(..) => typ.clone(),
+    }
+}
+
+// Integers count as true when they are not 0
+fn check_condition(typ: &Type, sym: &DebugSym) {
+    if !is_bool(typ) && typ.int_info().is_none() {
+        eprintln!("Error: Condition must be a bool or an integer, not {} at line {}", typ, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+}
+
+fn is_float(typ: &Type) -> bool {
+    matches!(typ.strip_const(), Type::Named(name) if name == "float32" || name == "float64")
+}
+
+fn is_float64(typ: &Type) -> bool {
+    *typ.strip_const() == Type::Named(String::from("float64"))
+}
+
+// Type arithmetic on a float and another number is done in, float32 unless one of them is a float64
+fn common_float_type(lhs: &Type, rhs: &Type) -> Type {
+    if is_float64(lhs) || is_float64(rhs) {
+        Type::Named(String::from("float64"))
+    } else {
+        Type::Named(String::from("float32"))
+    }
+}
+
+// Any two of integers, floats and pointers can be cast to each other, except for floats and pointers.
+// Bools only cast to and from integers.
+fn check_cast(from: &Type, to: &Type, linker: &Linker, sym: &DebugSym) {
+    let is_ptr = |typ: &Type| matches!(typ.strip_const(), Type::Ptr(_));
+    // Enums only convert to and from integers
+    if get_enum(from, linker).is_some() || get_enum(to, linker).is_some() {
+        if from.strip_const() != to.strip_const() && from.int_info().is_none() && to.int_info().is_none() {
+            eprintln!("Error: Cannot cast {} to {} at line {}", from, to, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+        return;
+    }
+    let is_scalar = |typ: &Type| typ.int_info().is_some() || is_float(typ) || is_ptr(typ);
+    if from.strip_const() == to.strip_const() {
+        return;
+    }
+    if is_bool(from) || is_bool(to) {
+        if from.int_info().is_none() && to.int_info().is_none() {
+            eprintln!("Error: Cannot cast {} to {} at line {}", from, to, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+        return;
+    }
+    if !is_scalar(from) || !is_scalar(to) || (is_float(from) && is_ptr(to)) || (is_ptr(from) && is_float(to)) {
+        eprintln!("Error: Cannot cast {} to {} at line {}", from, to, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+}
+
+fn make_const(typ: Type) -> Type {
+    if typ.is_const() {
+        typ
+    } else {
+        Type::Const(Box::new(typ))
+    }
+}
+
+// Rejects assignments that would leave the stack unbalanced, e.g. an integer into a struct
+fn check_assign(typ: &Type, expr: &Expr, linker: &Linker, vars: &VarStack, func_args: &Option<VarStack>, sym: &DebugSym) {
+    if let (Type::Arr(elem_type, len), Expr::Array(items)) = (typ.unalias(), expr) {
+        if len.is_some_and(|len| items.len() as u64 > len) {
+            eprintln!("Error: Too many elements in initializer for {} at line {}", typ, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+        items.iter().for_each(|item| check_assign(elem_type, item, linker, vars, func_args, sym));
+        return;
+    }
+
+    let expr_type = get_expr_type(expr, linker, vars, func_args, sym);
+    if (is_passed_by_pointer(typ, linker) || is_passed_by_pointer(&expr_type, linker)) && typ.strip_const() != expr_type.strip_const() {
+        eprintln!("Error: Cannot assign {} to {} at line {}", expr_type, typ, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+
+    // A pointer to a constant cannot become a pointer that allows writes
+    if let (Type::Ptr(target), Type::Ptr(value)) = (typ.strip_const(), expr_type.strip_const()) {
+        if value.is_const() && !target.is_const() {
+            eprintln!("Error: Assigning {} to {} discards const at line {}", expr_type, typ, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+    }
+
+    if implicit_conversion(&expr_type, typ).is_none() {
+        eprintln!(
+            "Error: Cannot implicitly convert {} to {} at line {}, this needs a cast",
+            expr_type, typ, sym.lineno
+        );
+        eprintln!("{}: {}", sym.lineno, sym.val);
+        exit(1)
+    }
+
+    if let Some(warning) = narrowing_warning(expr, &expr_type, typ) {
+        eprintln!("Warning: {} at line {}", warning, sym.lineno);
+        eprintln!("{}: {}", sym.lineno, sym.val)
+    }
+}
+
+// Pops a value of `size` words off the stack into `location`
+fn compile_store(location: &Location, size: u64) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    write!(s, "{}", location.code)?;
+    for word in 0..size {
+        writeln!(s, "POP R2")?;
+        writeln!(s, "LSTR {} {} R2", location.base, location.offset + word as i64)?
+    }
+    writeln!(s)?;
+    Ok(s)
+}
+
+// A value in memory at `base + offset`, where `base` is only valid after `code` has run
+struct Location {
+    code: String,
+    base: &'static str,
+    offset: i64,
+}
+
+fn get_location(
+    expr: &Expr,
+    linker: &mut Linker,
+    vars: &VarStack,
+    func_args: &Option<VarStack>,
+    strings: &mut StringsContainer,
+    sym: &DebugSym,
+) -> Location {
+    match expr {
+        Expr::Ident(name) => {
+            if let Some(offset) = vars.get_offset(name) {
+                return Location {
+                    code: String::new(),
+                    base: "R1",
+                    offset: -(offset as i64),
+                };
+            }
+            if let Some(func_args) = func_args {
+                if let Some(offset) = func_args.get_offset(name) {
+                    // Struct and array arguments are pointers to the caller's copy
+                    if is_passed_by_pointer(&func_args.get_type(name).unwrap(), linker) {
+                        return Location {
+                            code: format!("LLOD R3 R1 {}\n", offset + 1),
+                            base: "R3",
+                            offset: 0,
+                        };
+                    }
+                    // Arguments sit above the return address, `offset` is where the argument ends
+                    let size = linker.size_of(&func_args.get_type(name).unwrap());
+                    return Location {
+                        code: String::new(),
+                        base: "R1",
+                        offset: (offset + 2 - size) as i64,
+                    };
+                }
+            }
+            if let Some(global) = linker.get_global(name) {
+                return Location {
+                    code: format!("IMM R3 {}\n", global.get_label()),
+                    base: "R3",
+                    offset: 0,
+                };
+            }
+
+            eprintln!("Error: Undefined variable {} at line {}", name, sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+
+        Expr::Member { expr: inner, field } => {
+            let (_, field_offset) = get_field(&get_expr_type(inner, linker, vars, func_args, sym), field, linker, sym);
+            let mut location = get_location(inner, linker, vars, func_args, strings, sym);
+            location.offset += field_offset as i64;
+            location
+        }
+
+        Expr::Index { expr: inner, index } => {
+            let elem_size = linker.size_of(&get_expr_type(expr, linker, vars, func_args, sym));
+            if let (Type::Arr(_, Some(len)), Expr::Number(idx)) = (get_expr_type(inner, linker, vars, func_args, sym).strip_const(), &**index) {
+                if *idx < 0 || *idx as u64 >= *len {
+                    eprintln!("Error: Index {} is out of bounds for array of length {} at line {}", idx, len, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+            }
+
+            // Pointers hold the address of the first element instead of being it
+            let location = if let Type::Ptr(_) = get_expr_type(inner, linker, vars, func_args, sym).strip_const() {
+                Location {
+                    code: compile_expr(inner, linker, vars, func_args, strings, 32, sym).unwrap() + "POP R3\n",
+                    base: "R3",
+                    offset: 0,
+                }
+            } else {
+                get_location(inner, linker, vars, func_args, strings, sym)
+            };
+            // Addresses are a single word, so 64 bit indices only keep their low word
+            let index_type = promote_bool(&promote_enum(&get_expr_type(index, linker, vars, func_args, sym), linker));
+            if index_type.int_info().is_none() {
+                eprintln!("Error: Index must be an integer, not {} at line {}", index_type, sym.lineno);
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            let index_code = compile_converted(index, &Type::Named(String::from("int32")), linker, vars, func_args, strings, sym).unwrap();
+
+            // The address of the element goes into R3, the static part of the offset stays as is
+            let mut code = String::new();
+            if location.code.is_empty() {
+                code += &index_code;
+                code += "POP R3\n";
+                if elem_size != 1 {
+                    code += &format!("MLT R3 R3 {}\n", elem_size)
+                }
+                code += &format!("ADD R3 R3 {}\n", location.base)
+            } else {
+                code += &location.code;
+                code += "PSH R3\n";
+                code += &index_code;
+                code += "POP R2\n";
+                code += "POP R3\n";
+                if elem_size != 1 {
+                    code += &format!("MLT R2 R2 {}\n", elem_size)
+                }
+                code += "ADD R3 R3 R2\n"
+            }
+
+            Location {
+                code,
+                base: "R3",
+                offset: location.offset,
+            }
+        }
+
+        Expr::Deref(inner) => {
+            if !matches!(get_expr_type(inner, linker, vars, func_args, sym).strip_const(), Type::Ptr(_)) {
+                eprintln!(
+                    "Error: Cannot dereference non-pointer type {} at line {}",
+                    get_expr_type(inner, linker, vars, func_args, sym),
+                    sym.lineno
+                );
+                eprintln!("{}: {}", sym.lineno, sym.val);
+                exit(1)
+            }
+            Location {
+                code: compile_expr(inner, linker, vars, func_args, strings, 32, sym).unwrap() + "POP R3\n",
+                base: "R3",
+                offset: 0,
+            }
+        }
+
+        _ => {
+            eprintln!("Error: Expression cannot be assigned to or have its address taken at line {}", sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+    }
+}
+
+fn get_op_str(op: &Operation) -> &'static str {
+    match op {
+        Operation::Add => "ADD",
+        Operation::Sub => "SUB",
+        Operation::Mult => "MLT",
+        Operation::Div => "DIV",
+        Operation::Mod => "MOD",
+        Operation::BitAnd => "AND",
+        Operation::BitOr => "OR",
+        Operation::BitXor => "XOR",
+        Operation::Shl => "BSL",
+        Operation::Shr => "BSR",
+    }
+}
+
+// Calls a runtime routine and removes its operands from the stack, the result is left in R2 and R3
+fn compile_runtime_call(routine: &str, arg_words: u64, linker: &mut Linker) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    linker.use_runtime(routine);
+    writeln!(s, "CAL .{}", routine)?;
+    writeln!(s, "ADD SP SP {}", arg_words)?;
+    Ok(s)
+}
+
+// Combines the two 64 bit operands on the stack into their result, the amount of a shift is a single word
+fn compile_wide_op(op: &Operation, signed: bool, linker: &mut Linker) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+
+    let routine = match op {
+        Operation::Mult => Some("rt_mul64"),
+        Operation::Div | Operation::Mod if signed => Some("rt_sdivmod64"),
+        Operation::Div | Operation::Mod => Some("rt_udivmod64"),
+        Operation::Shl => Some("rt_shl64"),
+        Operation::Shr if signed => Some("rt_sar64"),
+        Operation::Shr => Some("rt_shr64"),
+        _ => None,
+    };
+
+    if let Some(routine) = routine {
+        linker.use_runtime(routine);
+        writeln!(s, "CAL .{}", routine)?;
+        writeln!(s, "ADD SP SP {}", if let Operation::Shl | Operation::Shr = op { 3 } else { 4 })?;
+        // The division routines leave the remainder in R4 and R5
+        if let Operation::Mod = op {
+            writeln!(s, "MOV R2 R4")?;
+            writeln!(s, "MOV R3 R5")?
+        }
+    } else {
+        // Low words go into R2 and R3, high words into R4 and R5
+        writeln!(s, "POP R3")?;
+        writeln!(s, "POP R5")?;
+        writeln!(s, "POP R2")?;
+        writeln!(s, "POP R4")?;
+        match op {
+            // A low word carried exactly when the sum is smaller than an operand, SETL gives -1 then
+            Operation::Add => {
+                writeln!(s, "ADD R2 R2 R3")?;
+                writeln!(s, "SETL R6 R2 R3")?;
+                writeln!(s, "ADD R4 R4 R5")?;
+                writeln!(s, "SUB R4 R4 R6")?
+            }
+            Operation::Sub => {
+                writeln!(s, "SETL R6 R2 R3")?;
+                writeln!(s, "SUB R2 R2 R3")?;
+                writeln!(s, "SUB R4 R4 R5")?;
+                writeln!(s, "ADD R4 R4 R6")?
+            }
+            _ => {
+                writeln!(s, "{} R2 R2 R3", get_op_str(op))?;
+                writeln!(s, "{} R4 R4 R5", get_op_str(op))?
+            }
+        }
+        writeln!(s, "MOV R3 R4")?
+    }
+    writeln!(s, "PSH R3")?;
+    writeln!(s, "PSH R2\n")?;
+
+    Ok(s)
+}
+
+// Computes `R2 op rhs` into R2, where `rhs` is a register or an immediate, signed division rounds towards zero
+fn compile_int_op(op: &Operation, signed: bool, rhs: &str) -> Result<String, std::fmt::Error> {
+    let mut s = String::new();
+    match op {
+        Operation::Div if signed => writeln!(s, "SDIV R2 R2 {}", rhs)?,
+        // There is no signed modulo instruction, so the remainder is taken from the quotient
+        Operation::Mod if signed => {
+            writeln!(s, "SDIV R5 R2 {}", rhs)?;
+            writeln!(s, "MLT R5 R5 {}", rhs)?;
+            writeln!(s, "SUB R2 R2 R5")?
+        }
+        // Signed values keep their sign when shifted right
+        Operation::Shr if signed => writeln!(s, "BSS R2 R2 {}", rhs)?,
+        _ => writeln!(s, "{} R2 R2 {}", get_op_str(op), rhs)?,
+    }
+    Ok(s)
+}
+
+fn get_comp_set_str(comp: &Comparison, signed: bool) -> &'static str {
+    match (comp, signed) {
+        (Comparison::EQ, _) => "SETE",
+        (Comparison::NEQ, _) => "SETNE",
+        (Comparison::LT, false) => "SETL",
+        (Comparison::LTE, false) => "SETLE",
+        (Comparison::GT, false) => "SETG",
+        (Comparison::GTE, false) => "SETGE",
+        (Comparison::LT, true) => "SSETL",
+        (Comparison::LTE, true) => "SSETLE",
+        (Comparison::GT, true) => "SSETG",
+        (Comparison::GTE, true) => "SSETGE",
+    }
+}
+
+// Branch taken when the comparison is false
+fn get_inv_branch_str(comp: &Comparison, signed: bool) -> &'static str {
+    match (comp, signed) {
+        (Comparison::EQ, _) => "BNE",
+        (Comparison::NEQ, _) => "BRE",
+        (Comparison::LT, false) => "BGE",
+        (Comparison::LTE, false) => "BRG",
+        (Comparison::GT, false) => "BLE",
+        (Comparison::GTE, false) => "BRL",
+        (Comparison::LT, true) => "SBGE",
+        (Comparison::LTE, true) => "SBRG",
+        (Comparison::GT, true) => "SBLE",
+        (Comparison::GTE, true) => "SBRL",
+    }
+}
+
+// Quotes a string for DW, characters without an escape sequence are written as plain numbers
+fn urcl_string(string: &str) -> String {
+    let mut parts = vec![];
+    let mut quoted = String::new();
+    for c in string.chars() {
+        match c {
+            '\n' => quoted += "\\n",
+            '\t' => quoted += "\\t",
+            '\0' => quoted += "\\0",
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if c.is_control() => {
+                if !quoted.is_empty() {
+                    parts.push(format!("\"{}\"", quoted));
+                    quoted.clear()
+                }
+                parts.push((c as u32).to_string())
+            }
+            c => quoted.push(c),
+        }
+    }
+    if !quoted.is_empty() || parts.is_empty() {
+        parts.push(format!("\"{}\"", quoted))
+    }
+    parts.join(" ")
+}
+
+// Labels have to stay unique across imported modules too, as all of their code ends up in one file
+fn new_label_id() -> u64 {
+    static LABEL_COUNT: AtomicU64 = AtomicU64::new(0);
+    LABEL_COUNT.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(Clone, Copy)]
-pub struct AstCompileArgs {
+pub struct AstCompileArgs<'a> {
     pub debug_symbols: bool,
     pub standalone: bool,
     pub pop_frame: bool,
     pub opt_level: u32,
+    pub loop_info: Option<LoopInfo>,
+    // Innermost switch inside of the innermost loop, `break` leaves it instead of the loop
+    pub switch_info: Option<LoopInfo>,
+    // Return type of the function being compiled, returned values are converted to it
+    pub ret_type: Option<&'a Type>,
+    // Name of the function being compiled, used in errors about its return statements
+    pub func_name: Option<&'a str>,
+}
+
+// Innermost loop or switch being compiled, used by `break` and `continue`
+#[derive(Clone, Copy)]
+pub struct LoopInfo {
+    // Loop labels are `.loop{id}_continue` and `.loop{id}_end`, switches end at `.switch{id}_end`
+    pub id: u64,
+    // Stack slots that were in use when the loop was entered
+    pub stack_size: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -429,8 +2965,10 @@ impl VarStack {
         }
     }
 
-    pub fn push(&mut self, name: String, typ: &Type) {
-        self.vars.push((Variable { name, typ: typ.clone() }, (self.vars.len() + 1) as u64));
+    // Offsets point at the first (lowest addressed) word of a variable
+    pub fn push(&mut self, name: String, typ: &Type, size: u64) {
+        let offset = self.size() + size;
+        self.vars.push((Variable { name, typ: typ.clone() }, offset));
         let len = self.frames.len() - 1;
         self.frames[len] += 1
     }
@@ -445,15 +2983,22 @@ impl VarStack {
         self.frames.push(0)
     }
 
+    // Returns the amount of stack slots freed
     pub fn pop_frame(&mut self) -> u64 {
-        let len = self.frames.len() - 1;
-        let frame_count = self.frames[len];
+        let frame_count = self.frames.pop().unwrap();
+        let size = self.size();
         self.pop(frame_count);
-        frame_count
+        size - self.size()
+    }
+
+    // Number of stack slots taken up by all variables in scope
+    pub fn size(&self) -> u64 {
+        self.vars.last().map_or(0, |(_, offset)| *offset)
     }
 
+    // Inner scopes come last, so searching from the end finds the variable that shadows the others
     pub fn get_offset(&self, name: &String) -> Option<u64> {
-        for var in &self.vars {
+        for var in self.vars.iter().rev() {
             if var.0.name == *name {
                 return Some(var.1);
             }
@@ -462,11 +3007,22 @@ impl VarStack {
     }
 
     pub fn get_type(&self, name: &String) -> Option<Type> {
-        for (var, _) in &self.vars {
+        for (var, _) in self.vars.iter().rev() {
             if var.name == *name {
                 return Some(var.typ.clone());
             }
         }
         None
     }
+
+    // Whether `name` is already defined in the innermost frame
+    pub fn in_current_frame(&self, name: &String) -> bool {
+        let frame_count = *self.frames.last().unwrap() as usize;
+        self.vars.iter().rev().take(frame_count).any(|(var, _)| var.name == *name)
+    }
+
+    // Whether the innermost frame is the outermost one, e.g. the body of a function
+    pub fn is_outermost_frame(&self) -> bool {
+        self.frames.len() == 1
+    }
 }
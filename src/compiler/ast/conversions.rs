@@ -0,0 +1,84 @@
+// Implicit conversion rules, shared by the type checker and overload resolution.
+//
+// A value of type `from` can be used where `to` is expected if one of these rules applies,
+// listed from best to worst. Constness of the value itself does not matter when it gets copied,
+// constant structs passed by address are rejected when the call is compiled.
+//
+// Exact:
+//   - the types are the same
+//   - an untyped integer literal is used as an int32
+//   - an array is used as an array of the same element type without a length
+// Promotion, nothing can be lost:
+//   - an integer is used as a wider integer that holds all of its values,
+//     so signed types only widen to signed types, chars count as uint8
+//   - an untyped integer literal is used as any other integer, literals that do not fit get a warning
+//   - a bool is used as an integer, true being 1
+//   - a float32 is used as a float64
+//   - a `T*` is used as a `const T*`
+//   - an array is used as a pointer to its first element
+// Conversion, can lose information and gets a warning where it might:
+//   - an integer is used as any other integer, narrowing truncates and keeps the low bits
+//   - an integer is used as a float and the other way around, floats are truncated towards zero
+//   - a float64 is used as a float32, which rounds it to the nearest float32
+//   - an untyped integer literal is used as a pointer
+//
+// Everything else, like turning pointers or integers into bools or changing the pointed to type, needs a cast.
+
+use super::nodes::Type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    Exact,
+    Promotion,
+    Conversion,
+}
+
+pub fn implicit_conversion(from: &Type, to: &Type) -> Option<Rank> {
+    let from = from.strip_const();
+    let to = to.strip_const();
+    let literal = *from == Type::Named(String::from("int"));
+
+    match (from, to) {
+        _ if from == to => Some(Rank::Exact),
+        _ if literal && *to == Type::Named(String::from("int32")) => Some(Rank::Exact),
+        (Type::Arr(from_elem, _), Type::Arr(to_elem, None)) if from_elem == to_elem => Some(Rank::Exact),
+
+        (Type::Ptr(from_target), Type::Ptr(to_target)) if to_target.is_const() && from_target.strip_const() == to_target.strip_const() => {
+            Some(Rank::Promotion)
+        }
+        (Type::Arr(from_elem, _), Type::Ptr(to_target)) if from_elem.strip_const() == to_target.strip_const() => {
+            // Elements of a constant array cannot be written through the pointer
+            if from_elem.is_const() && !to_target.is_const() {
+                None
+            } else {
+                Some(Rank::Promotion)
+            }
+        }
+        (_, Type::Ptr(_)) if literal => Some(Rank::Conversion),
+
+        _ => match (from.int_info(), to.int_info()) {
+            _ if literal && to.int_info().is_some() => Some(Rank::Promotion),
+            (None, Some(_)) if *from == Type::Named(String::from("bool")) => Some(Rank::Promotion),
+            (Some((from_width, from_signed)), Some((to_width, to_signed))) => {
+                if to_width > from_width && (to_signed || !from_signed) {
+                    Some(Rank::Promotion)
+                } else {
+                    Some(Rank::Conversion)
+                }
+            }
+            (Some(_), None) | (None, Some(_)) if is_float(from) || is_float(to) => Some(Rank::Conversion),
+            (None, None) if is_float(from) && is_float(to) => {
+                if *to == Type::Named(String::from("float64")) {
+                    Some(Rank::Promotion)
+                } else {
+                    Some(Rank::Conversion)
+                }
+            }
+            _ => None,
+        },
+    }
+}
+
+fn is_float(typ: &Type) -> bool {
+    matches!(typ, Type::Named(name) if name == "float32" || name == "float64")
+}
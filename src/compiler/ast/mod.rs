@@ -1,5 +1,8 @@
+#[allow(clippy::module_inception)]
 mod ast;
 pub use ast::*;
 pub mod ast_compiler;
+pub mod conversions;
 pub mod nodes;
 pub mod optimizer;
+pub mod patterns;
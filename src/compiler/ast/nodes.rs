@@ -31,7 +31,14 @@ pub enum Node {
         expr: Option<Expr>,
     },
     VarAssign {
-        ident: String,
+        // Any assignable expression: a variable, struct field, array element or dereferenced pointer
+        lhs: Expr,
+        expr: Expr,
+    },
+    // `lhs op= expr`, also used for `lhs++;` and `lhs--;`
+    CompoundAssign {
+        lhs: Expr,
+        op: Operation,
         expr: Expr,
     },
     Function {
@@ -44,25 +51,159 @@ pub enum Node {
         name: String,
         args: Vec<Expr>,
     },
+    Struct {
+        name: String,
+        fields: Vec<(Type, String)>,
+    },
+    Enum {
+        name: String,
+        // Variants without a value take the one of the previous variant plus one, starting at 0
+        variants: Vec<(String, Option<Expr>)>,
+    },
     While {
         cond: Expr,
         body: Program,
     },
+    For {
+        // At most one statement, its variable is only visible inside the loop
+        init: Program,
+        cond: Option<Expr>,
+        step: Program,
+        body: Program,
+    },
+    DoWhile {
+        body: Program,
+        cond: Expr,
+    },
     If {
         cond: Expr,
         body: Program,
+        // `else if` chains are stored as an else body holding a single `If`
+        else_body: Option<Program>,
+    },
+    Union {
+        name: String,
+        // Each variant has its own fields, which share the space after the tag
+        variants: Vec<(String, Vec<(Type, String)>)>,
+    },
+    Match {
+        expr: Expr,
+        arms: Vec<(DebugSym, Pattern, Program)>,
+    },
+    Switch {
+        expr: Expr,
+        // `None` is the `default` case, cases fall through to the next one unless they `break`
+        cases: Vec<(DebugSym, Option<Expr>, Program)>,
     },
+    // `{ ... }` on its own, its variables are only visible inside of it
+    Block(Program),
+    Break,
+    Continue,
     Return(Option<Expr>),
+    // `typedef typ name;` or `type name = typ;`
+    Typedef {
+        name: String,
+        typ: Type,
+    },
     Import(Vec<String>),
     InlineURCL(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug)]
+pub enum Pattern {
+    // `Variant(a, b)` binds the fields to new variables, `_` in place of a name skips a field.
+    // A bare `Variant` ignores all of them.
+    Variant { name: String, bindings: Option<Vec<String>> },
+    // `_` matches everything the other arms do not
+    Wildcard,
+}
+
+#[derive(Debug, Clone)]
 pub enum Type {
     Named(String),
     Ptr(Box<Type>),
-    Arr(Box<Type>),
+    // Length is only left out for array arguments
+    Arr(Box<Type>, Option<u64>),
+    // Length given by a constant expression and its source text, evaluated once the constants are known
+    ArrExpr(Box<Type>, Box<Expr>, String),
     Const(Box<Type>),
+    // A type alias by its name and what it stands for, which is only kept to show the name in messages
+    Alias(String, Box<Type>),
+}
+
+impl Type {
+    // Constness only restricts writes and aliases only rename, so layout and operators look through both
+    pub fn strip_const(&self) -> &Type {
+        match self {
+            Self::Const(typ) | Self::Alias(_, typ) => typ.strip_const(),
+            typ => typ,
+        }
+    }
+
+    pub fn unalias(&self) -> &Type {
+        match self {
+            Self::Alias(_, typ) => typ.unalias(),
+            typ => typ,
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        matches!(self.unalias(), Self::Const(_))
+    }
+
+    // Size in words of the types that need no user definition to be looked up
+    pub fn builtin_size(&self) -> Option<u64> {
+        match self {
+            Self::Named(name) => match name.as_str() {
+                "void" => Some(0),
+                "int8" | "int16" | "int32" | "uint8" | "uint16" | "uint32" | "int" => Some(1),
+                // Two words, low word first
+                "int64" | "uint64" | "float64" => Some(2),
+                "float32" | "string" | "char" | "bool" => Some(1),
+                _ => None,
+            },
+            // Unsized arrays only exist as arguments, which are passed as pointers
+            Self::Ptr(_) | Self::Arr(_, None) => Some(1),
+            Self::Arr(typ, Some(len)) => typ.builtin_size().map(|size| len * size),
+            Self::ArrExpr(..) => None,
+            Self::Const(typ) | Self::Alias(_, typ) => typ.builtin_size(),
+        }
+    }
+
+    // Width in bits and signedness of integer types, chars count as unsigned bytes and untyped literals as int32
+    pub fn int_info(&self) -> Option<(u32, bool)> {
+        match self.strip_const() {
+            Self::Named(name) => match name.as_str() {
+                "int8" => Some((8, true)),
+                "int16" => Some((16, true)),
+                "int32" | "int" => Some((32, true)),
+                "int64" => Some((64, true)),
+                "uint8" | "char" => Some((8, false)),
+                "uint16" => Some((16, false)),
+                "uint32" => Some((32, false)),
+                "uint64" => Some((64, false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Untyped literals are computed as int32s, unless one of them needs more than 32 bits and makes it int64s
+pub fn is_wide_literal(num: i64) -> bool {
+    num < i32::MIN as i64 || num > u32::MAX as i64
+}
+
+// An alias is the same type as the one it stands for
+impl PartialEq for Type {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.unalias(), other.unalias()) {
+            (Self::Named(lhs), Self::Named(rhs)) => lhs == rhs,
+            (Self::Ptr(lhs), Self::Ptr(rhs)) | (Self::Const(lhs), Self::Const(rhs)) => lhs == rhs,
+            (Self::Arr(lhs, lhs_len), Self::Arr(rhs, rhs_len)) => lhs == rhs && lhs_len == rhs_len,
+            _ => false,
+        }
+    }
 }
 
 impl Display for Type {
@@ -70,8 +211,13 @@ impl Display for Type {
         match self {
             Self::Named(name) => write!(f, "{}", name)?,
             Self::Ptr(typ) => write!(f, "{}*", *typ)?,
-            Self::Arr(typ) => write!(f, "{}[]", *typ)?,
+            Self::Arr(typ, Some(len)) => write!(f, "{}[{}]", *typ, len)?,
+            Self::Arr(typ, None) => write!(f, "{}[]", *typ)?,
+            Self::ArrExpr(typ, _, len) => write!(f, "{}[{}]", *typ, len)?,
+            // The pointer itself is constant, not what it points to
+            Self::Const(typ) if matches!(**typ, Self::Ptr(_)) => write!(f, "{} const", *typ)?,
             Self::Const(typ) => write!(f, "const {}", *typ)?,
+            Self::Alias(name, _) => write!(f, "{}", name)?,
         }
 
         Ok(())
@@ -85,8 +231,14 @@ pub enum Operation {
     Mult,
     Div,
     Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy)]
 pub enum Comparison {
     EQ,
@@ -97,12 +249,86 @@ pub enum Comparison {
     GTE,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum Logic {
+    And,
+    Or,
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr {
     Number(i64),
+    Float(f64),
+    Char(char),
+    Bool(bool),
     Ident(String),
     Str(String),
-    BiOp { lhs: Box<Expr>, op: Operation, rhs: Box<Expr> },
-    FuncCall { name: String, args: Vec<Expr> },
-    Comp { lhs: Box<Expr>, comp: Comparison, rhs: Box<Expr> },
+    // `name::variant`, also a union variant without fields
+    EnumValue {
+        name: String,
+        variant: String,
+    },
+    // `name::variant(args)` makes a union value
+    UnionValue {
+        name: String,
+        variant: String,
+        args: Vec<Expr>,
+    },
+    BiOp {
+        lhs: Box<Expr>,
+        op: Operation,
+        rhs: Box<Expr>,
+    },
+    FuncCall {
+        name: String,
+        args: Vec<Expr>,
+    },
+    Comp {
+        lhs: Box<Expr>,
+        comp: Comparison,
+        rhs: Box<Expr>,
+    },
+    // Only evaluates `rhs` when `lhs` does not already decide the result
+    Logic {
+        lhs: Box<Expr>,
+        op: Logic,
+        rhs: Box<Expr>,
+    },
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    BitNot(Box<Expr>),
+    // `op` is `Add` for `++` and `Sub` for `--`
+    IncDec {
+        expr: Box<Expr>,
+        op: Operation,
+        prefix: bool,
+    },
+    Member {
+        expr: Box<Expr>,
+        field: String,
+    },
+    Index {
+        expr: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Array(Vec<Expr>),
+    AddrOf(Box<Expr>),
+    Deref(Box<Expr>),
+    // `expr as typ`
+    Cast {
+        expr: Box<Expr>,
+        typ: Type,
+    },
+    // `cond ? then : otherwise`, only the chosen branch is evaluated
+    Ternary {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+    // `sizeof expr`, the expression itself is never evaluated
+    SizeOf(Box<Expr>),
+    // `sizeof(typ)`
+    SizeOfType(Type),
+    // `alignof(typ)`
+    AlignOf(Type),
 }
@@ -1,118 +1,365 @@
-use std::process::exit;
+use std::{collections::HashMap, process::exit};
 
 use super::nodes::*;
 
 pub fn optimize(prog: &mut Program) {
+    optimize_block(prog, &mut HashMap::new())
+}
+
+// `consts` holds the values of the constant variables in scope, which get folded into their uses
+fn optimize_block(prog: &mut Program, consts: &mut HashMap<String, Expr>) {
     for (sym, stmt) in &mut prog.statements {
         match stmt {
-            Node::VarDefine {
-                typ: _,
-                ident: _,
-                expr: Some(expr),
-            } => *expr = optimize_expr(sym, expr),
+            Node::VarDefine { typ, ident, expr } => {
+                if let Some(expr) = expr {
+                    *expr = optimize_expr(sym, expr, consts)
+                }
+                // A new variable shadows any constant with the same name
+                consts.remove(ident);
+                // Pointers stay variables, a literal in their place would lose the pointed to type
+                if let (Type::Const(inner), Some(expr)) = (typ, expr) {
+                    let literal = match (inner.strip_const(), &*expr) {
+                        // Float literals are float32s and folded with their precision, float64 constants stay variables to keep theirs
+                        (Type::Named(name), Expr::Number(val)) if name == "float32" => Some(Expr::Float(*val as f32 as f64)),
+                        (Type::Named(name), Expr::Float(val)) if name == "float32" => Some(Expr::Float(*val as f32 as f64)),
+                        // Literals behave like int32, folding narrower or unsigned constants would change their arithmetic
+                        (Type::Named(name), Expr::Number(val)) if name == "int32" => Some(Expr::Number(*val)),
+                        (Type::Named(name), Expr::Bool(val)) if name == "bool" => Some(Expr::Bool(*val)),
+                        _ => None,
+                    };
+                    if let Some(literal) = literal {
+                        consts.insert(ident.clone(), literal);
+                    }
+                }
+            }
+
+            Node::VarAssign { lhs: _, expr } | Node::CompoundAssign { lhs: _, op: _, expr } => *expr = optimize_expr(sym, expr, consts),
 
             Node::Function {
                 ret_type: _,
                 name: _,
-                args: _,
+                args,
                 body,
-            } => optimize(body),
+            } => {
+                let mut consts = consts.clone();
+                args.iter().for_each(|(_, name)| {
+                    consts.remove(name);
+                });
+                optimize_block(body, &mut consts)
+            }
 
             Node::FuncCall { name: _, args } => {
                 for arg in args {
-                    *arg = optimize_expr(sym, arg)
+                    *arg = optimize_expr(sym, arg, consts)
                 }
             }
 
-            _ => {}
-        }
-    }
-}
+            Node::Return(Some(expr)) => *expr = optimize_expr(sym, expr, consts),
 
-fn optimize_expr(sym: &DebugSym, expr: &Expr) -> Expr {
-    match expr {
-        Expr::BiOp { lhs, op, rhs } => {
-            let lhs_opt = optimize_expr(sym, lhs);
-            let rhs_opt = optimize_expr(sym, rhs);
-
-            match op {
-                Operation::Add => {
-                    if let Expr::Number(val1) = lhs_opt {
-                        if let Expr::Number(val2) = rhs_opt {
-                            Expr::Number(val1 + val2)
-                        } else {
-                            expr.clone()
-                        }
-                    } else if let Expr::Str(string) = lhs_opt {
-                        match rhs_opt {
-                            Expr::Number(val) => Expr::Str(format!("{}{}", string, val)),
-                            Expr::Str(string2) => Expr::Str(string + string2.as_str()),
-                            _ => {
-                                eprintln!("Cannot perform string concatenation at line {}", sym.lineno);
-                                eprintln!("{}: {}", sym.lineno, sym.val);
-                                exit(1)
-                            }
-                        }
-                    } else {
-                        expr.clone()
-                    }
+            Node::While { cond, body } => {
+                *cond = optimize_expr(sym, cond, consts);
+                optimize_block(body, &mut consts.clone())
+            }
+
+            Node::For { init, cond, step, body } => {
+                // Variables defined in the init part are only visible inside the loop
+                let mut consts = consts.clone();
+                optimize_block(init, &mut consts);
+                if let Some(cond) = cond {
+                    *cond = optimize_expr(sym, cond, &consts)
                 }
+                optimize_block(step, &mut consts.clone());
+                optimize_block(body, &mut consts.clone())
+            }
 
-                Operation::Sub => {
-                    if let Expr::Number(val1) = lhs_opt {
-                        if let Expr::Number(val2) = rhs_opt {
-                            Expr::Number(val1 - val2)
-                        } else {
-                            expr.clone()
-                        }
-                    } else {
-                        expr.clone()
+            Node::Enum { name: _, variants } => {
+                for (_, value) in variants.iter_mut() {
+                    if let Some(value) = value {
+                        *value = optimize_expr(sym, value, consts)
                     }
                 }
+            }
 
-                Operation::Mult => {
-                    if let Expr::Number(val1) = lhs_opt {
-                        if let Expr::Number(val2) = rhs_opt {
-                            Expr::Number(val1 * val2)
-                        } else {
-                            expr.clone()
-                        }
-                    } else {
-                        expr.clone()
+            Node::Match { expr, arms } => {
+                *expr = optimize_expr(sym, expr, consts);
+                for (_, pattern, body) in arms.iter_mut() {
+                    // Bound fields shadow constants with the same name
+                    let mut consts = consts.clone();
+                    if let Pattern::Variant {
+                        bindings: Some(bindings), ..
+                    } = pattern
+                    {
+                        bindings.iter().for_each(|binding| {
+                            consts.remove(binding);
+                        });
                     }
+                    optimize_block(body, &mut consts)
                 }
+            }
 
-                Operation::Div => {
-                    if let Expr::Number(val1) = lhs_opt {
-                        if let Expr::Number(val2) = rhs_opt {
-                            if val2 == 0 {
-                                eprintln!("Error: Division by 0 after constant folding at line {}", sym.lineno);
-                                eprintln!("{}: {}", sym.lineno, sym.val);
-                                exit(1)
-                            }
-                            Expr::Number(val1 / val2)
-                        } else {
-                            expr.clone()
-                        }
-                    } else {
-                        expr.clone()
+            Node::Switch { expr, cases } => {
+                *expr = optimize_expr(sym, expr, consts);
+                for (case_sym, value, body) in cases.iter_mut() {
+                    if let Some(value) = value {
+                        *value = optimize_expr(case_sym, value, consts)
                     }
+                    optimize_block(body, &mut consts.clone())
                 }
+            }
+
+            Node::Block(body) => optimize_block(body, &mut consts.clone()),
+
+            Node::DoWhile { body, cond } => {
+                optimize_block(body, &mut consts.clone());
+                *cond = optimize_expr(sym, cond, consts)
+            }
+
+            Node::If { cond, body, else_body } => {
+                *cond = optimize_expr(sym, cond, consts);
+                optimize_block(body, &mut consts.clone());
+                if let Some(else_body) = else_body {
+                    optimize_block(else_body, &mut consts.clone())
+                }
+            }
 
-                Operation::Mod => {
-                    if let Expr::Number(val1) = lhs_opt {
-                        if let Expr::Number(val2) = rhs_opt {
-                            Expr::Number(val1 % val2)
-                        } else {
-                            expr.clone()
-                        }
-                    } else {
-                        expr.clone()
+            _ => {}
+        }
+    }
+}
+
+fn optimize_expr(sym: &DebugSym, expr: &Expr, consts: &HashMap<String, Expr>) -> Expr {
+    match expr {
+        Expr::BiOp { lhs, op, rhs } => {
+            let lhs_opt = optimize_expr(sym, lhs, consts);
+            let rhs_opt = optimize_expr(sym, rhs, consts);
+            // Keeps folded operands even when the whole expression cannot be folded
+            let unfolded = |lhs, rhs| Expr::BiOp {
+                lhs: Box::new(lhs),
+                op: *op,
+                rhs: Box::new(rhs),
+            };
+
+            if let Some((val1, val2)) = float_operands(&lhs_opt, &rhs_opt) {
+                return match op {
+                    Operation::Add => Expr::Float((val1 + val2) as f64),
+                    Operation::Sub => Expr::Float((val1 - val2) as f64),
+                    Operation::Mult => Expr::Float((val1 * val2) as f64),
+                    Operation::Div => Expr::Float((val1 / val2) as f64),
+                    _ => unfolded(lhs_opt, rhs_opt),
+                };
+            }
+
+            match (op, &lhs_opt, &rhs_opt) {
+                (Operation::Add, Expr::Str(string), _) => match &rhs_opt {
+                    Expr::Number(val) => Expr::Str(format!("{}{}", string, val)),
+                    Expr::Str(string2) => Expr::Str(string.clone() + string2.as_str()),
+                    _ => {
+                        eprintln!("Cannot perform string concatenation at line {}", sym.lineno);
+                        eprintln!("{}: {}", sym.lineno, sym.val);
+                        exit(1)
                     }
+                },
+
+                (Operation::Div | Operation::Mod, Expr::Number(_), Expr::Number(0)) => {
+                    let name = if let Operation::Div = op { "Division" } else { "Modulo" };
+                    eprintln!("Error: {} by 0 after constant folding at line {}", name, sym.lineno);
+                    eprintln!("{}: {}", sym.lineno, sym.val);
+                    exit(1)
+                }
+
+                // Overflowing divisions are left to the target
+                (_, Expr::Number(val1), Expr::Number(val2)) => match fold_int(op, *val1, *val2) {
+                    Some(val) => Expr::Number(val),
+                    None => unfolded(lhs_opt, rhs_opt),
+                },
+
+                _ => unfolded(lhs_opt, rhs_opt),
+            }
+        }
+
+        Expr::Comp { lhs, comp, rhs } => {
+            let lhs_opt = optimize_expr(sym, lhs, consts);
+            let rhs_opt = optimize_expr(sym, rhs, consts);
+
+            if let Some((val1, val2)) = float_operands(&lhs_opt, &rhs_opt) {
+                let res = match comp {
+                    Comparison::EQ => val1 == val2,
+                    Comparison::NEQ => val1 != val2,
+                    Comparison::LT => val1 < val2,
+                    Comparison::LTE => val1 <= val2,
+                    Comparison::GT => val1 > val2,
+                    Comparison::GTE => val1 >= val2,
+                };
+                Expr::Bool(res)
+            } else if let (Expr::Bool(val1), Expr::Bool(val2)) = (&lhs_opt, &rhs_opt) {
+                match comp {
+                    Comparison::EQ => Expr::Bool(val1 == val2),
+                    _ => Expr::Bool(val1 != val2),
+                }
+            } else if let (Expr::Number(val1), Expr::Number(val2)) = (&lhs_opt, &rhs_opt) {
+                let (val1, val2) = literal_operands(*val1, *val2);
+                let res = match comp {
+                    Comparison::EQ => val1 == val2,
+                    Comparison::NEQ => val1 != val2,
+                    Comparison::LT => val1 < val2,
+                    Comparison::LTE => val1 <= val2,
+                    Comparison::GT => val1 > val2,
+                    Comparison::GTE => val1 >= val2,
+                };
+                Expr::Bool(res)
+            } else {
+                Expr::Comp {
+                    lhs: Box::new(lhs_opt),
+                    comp: *comp,
+                    rhs: Box::new(rhs_opt),
                 }
             }
         }
 
+        Expr::Logic { lhs, op, rhs } => {
+            let lhs_opt = optimize_expr(sym, lhs, consts);
+            let rhs_opt = optimize_expr(sym, rhs, consts);
+
+            match (op, truth_value(&lhs_opt), truth_value(&rhs_opt)) {
+                (Logic::And, Some(false), _) => Expr::Bool(false),
+                (Logic::Or, Some(true), _) => Expr::Bool(true),
+                (_, Some(_), Some(val)) => Expr::Bool(val),
+                _ => Expr::Logic {
+                    lhs: Box::new(lhs_opt),
+                    op: *op,
+                    rhs: Box::new(rhs_opt),
+                },
+            }
+        }
+
+        // Sizes of user defined types and of expressions are left to the compiler, which knows them
+        Expr::SizeOfType(typ) => match typ.builtin_size() {
+            Some(size) => Expr::Number(size as i64),
+            None => expr.clone(),
+        },
+        Expr::AlignOf(typ) if typ.builtin_size().is_some() => Expr::Number(1),
+
+        Expr::Ternary { cond, then, otherwise } => {
+            let cond = optimize_expr(sym, cond, consts);
+            match truth_value(&cond) {
+                Some(true) => optimize_expr(sym, then, consts),
+                Some(false) => optimize_expr(sym, otherwise, consts),
+                None => Expr::Ternary {
+                    cond: Box::new(cond),
+                    then: Box::new(optimize_expr(sym, then, consts)),
+                    otherwise: Box::new(optimize_expr(sym, otherwise, consts)),
+                },
+            }
+        }
+
+        Expr::Not(inner) => {
+            let inner = optimize_expr(sym, inner, consts);
+            match truth_value(&inner) {
+                Some(val) => Expr::Bool(!val),
+                None => Expr::Not(Box::new(inner)),
+            }
+        }
+
+        Expr::Neg(inner) => match optimize_expr(sym, inner, consts) {
+            Expr::Number(val) if is_wide_literal(val) => Expr::Number(val.wrapping_neg()),
+            Expr::Number(val) => Expr::Number((val as i32).wrapping_neg() as i64),
+            Expr::Float(val) => Expr::Float(-val),
+            inner => Expr::Neg(Box::new(inner)),
+        },
+
+        Expr::BitNot(inner) => match optimize_expr(sym, inner, consts) {
+            Expr::Number(val) if is_wide_literal(val) => Expr::Number(!val),
+            Expr::Number(val) => Expr::Number(!(val as i32) as i64),
+            inner => Expr::BitNot(Box::new(inner)),
+        },
+
+        Expr::FuncCall { name, args } => Expr::FuncCall {
+            name: name.clone(),
+            args: args.iter().map(|arg| optimize_expr(sym, arg, consts)).collect(),
+        },
+
+        Expr::UnionValue { name, variant, args } => Expr::UnionValue {
+            name: name.clone(),
+            variant: variant.clone(),
+            args: args.iter().map(|arg| optimize_expr(sym, arg, consts)).collect(),
+        },
+
+        Expr::Index { expr, index } => Expr::Index {
+            expr: Box::new(optimize_expr(sym, expr, consts)),
+            index: Box::new(optimize_expr(sym, index, consts)),
+        },
+
+        Expr::Array(items) => Expr::Array(items.iter().map(|item| optimize_expr(sym, item, consts)).collect()),
+
+        // Constants still live on the stack, so their address can be taken
+        Expr::AddrOf(expr) => match **expr {
+            Expr::Ident(_) => Expr::AddrOf(expr.clone()),
+            _ => Expr::AddrOf(Box::new(optimize_expr(sym, expr, consts))),
+        },
+        Expr::Deref(expr) => Expr::Deref(Box::new(optimize_expr(sym, expr, consts))),
+        // Folding the cast itself would turn a typed value into an untyped literal
+        Expr::Cast { expr, typ } => Expr::Cast {
+            expr: Box::new(optimize_expr(sym, expr, consts)),
+            typ: typ.clone(),
+        },
+
+        Expr::Ident(name) => match consts.get(name) {
+            Some(val) => val.clone(),
+            None => expr.clone(),
+        },
+
         other => other.clone(),
     }
 }
+
+// Value of a constant condition, integers are true when they are not 0
+fn truth_value(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Bool(val) => Some(*val),
+        Expr::Number(val) => Some(*val != 0),
+        _ => None,
+    }
+}
+
+// Operands of a constant float operation, computed at the 32 bit precision of the target
+fn float_operands(lhs: &Expr, rhs: &Expr) -> Option<(f32, f32)> {
+    match (lhs, rhs) {
+        (Expr::Float(val1), Expr::Float(val2)) => Some((*val1 as f32, *val2 as f32)),
+        (Expr::Float(val1), Expr::Number(val2)) => Some((*val1 as f32, *val2 as f32)),
+        (Expr::Number(val1), Expr::Float(val2)) => Some((*val1 as f32, *val2 as f32)),
+        _ => None,
+    }
+}
+
+// Literal operands the way the target sees them, 32 bit literals are sign extended from their low word
+fn literal_operands(val1: i64, val2: i64) -> (i64, i64) {
+    if is_wide_literal(val1) || is_wide_literal(val2) {
+        (val1, val2)
+    } else {
+        (val1 as i32 as i64, val2 as i32 as i64)
+    }
+}
+
+// Integer operation on two literals, wrapping around at 32 or 64 bits like the generated code.
+// `None` when the target has to work it out itself, i.e. for the minimum value divided by -1.
+fn fold_int(op: &Operation, val1: i64, val2: i64) -> Option<i64> {
+    // Shifts keep the width of the shifted value
+    let wide = is_wide_literal(val1) || (is_wide_literal(val2) && !matches!(op, Operation::Shl | Operation::Shr));
+    let (val1, val2) = if wide { (val1, val2) } else { (val1 as i32 as i64, val2 as i32 as i64) };
+    let res = match op {
+        Operation::Add => val1.wrapping_add(val2),
+        Operation::Sub => val1.wrapping_sub(val2),
+        Operation::Mult => val1.wrapping_mul(val2),
+        Operation::Div if wide => val1.checked_div(val2)?,
+        Operation::Div => (val1 as i32).checked_div(val2 as i32)? as i64,
+        Operation::Mod if wide => val1.checked_rem(val2)?,
+        Operation::Mod => (val1 as i32).checked_rem(val2 as i32)? as i64,
+        Operation::BitAnd => val1 & val2,
+        Operation::BitOr => val1 | val2,
+        Operation::BitXor => val1 ^ val2,
+        // Shifting everything out leaves 0, or all ones for negative values shifted right
+        Operation::Shl => val1.checked_shl(val2 as u32).unwrap_or(0),
+        Operation::Shr => val1 >> val2.clamp(0, 63),
+    };
+    Some(if wide { res } else { res as i32 as i64 })
+}
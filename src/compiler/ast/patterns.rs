@@ -0,0 +1,68 @@
+// Checks the arms of a `match` against the union it matches on, before any code is generated for it.
+//
+// Every variant needs an arm, unless a `_` arm handles the rest. Patterns have to name a variant of the union
+// and bind all of its fields or none of them. Arms after a `_` or for a variant that was already matched
+// can never run, which only gets a warning.
+
+use std::process::exit;
+
+use super::{
+    super::linker::LinkerUnion,
+    nodes::{DebugSym, Pattern, Program},
+};
+
+pub fn check_match(union: &LinkerUnion, arms: &[(DebugSym, Pattern, Program)], sym: &DebugSym) {
+    let mut matched: Vec<&String> = vec![];
+    let mut wildcard = false;
+
+    for (arm_sym, pattern, _) in arms {
+        let unreachable = match pattern {
+            Pattern::Wildcard => wildcard || matched.len() == union.variants.len(),
+            Pattern::Variant { name, bindings } => {
+                let Some((_, fields)) = union.get_variant(name) else {
+                    eprintln!("Error: Union {} has no variant {} at line {}", union.name, name, arm_sym.lineno);
+                    eprintln!("{}: {}", arm_sym.lineno, arm_sym.val);
+                    exit(1)
+                };
+                if let Some(bindings) = bindings {
+                    if bindings.len() != fields.len() {
+                        eprintln!(
+                            "Error: Variant {}::{} has {} fields, but the pattern binds {} at line {}",
+                            union.name,
+                            name,
+                            fields.len(),
+                            bindings.len(),
+                            arm_sym.lineno
+                        );
+                        eprintln!("{}: {}", arm_sym.lineno, arm_sym.val);
+                        exit(1)
+                    }
+                }
+                wildcard || matched.contains(&name)
+            }
+        };
+        if unreachable {
+            eprintln!("Warning: Unreachable match arm at line {}", arm_sym.lineno);
+            eprintln!("{}: {}", arm_sym.lineno, arm_sym.val)
+        }
+
+        match pattern {
+            Pattern::Wildcard => wildcard = true,
+            Pattern::Variant { name, .. } => matched.push(name),
+        }
+    }
+
+    if !wildcard {
+        let missing = union
+            .variants
+            .iter()
+            .filter(|(name, _)| !matched.contains(&name))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            eprintln!("Error: Match on {} does not handle {} at line {}", union.name, missing.join(", "), sym.lineno);
+            eprintln!("{}: {}", sym.lineno, sym.val);
+            exit(1)
+        }
+    }
+}
@@ -44,6 +44,10 @@ pub fn compiler(args: &Args, linker: &mut Linker, importer: &mut ImportHelper) -
             standalone: args.no_main,
             pop_frame: false,
             opt_level: args.opt_level,
+            loop_info: None,
+            switch_info: None,
+            ret_type: None,
+            func_name: None,
         },
         linker,
         importer,
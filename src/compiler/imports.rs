@@ -62,7 +62,7 @@ impl ImportHelper {
                 }
                 not_found = false;
             } else if path.is_file() {
-                if self.imported.iter().map(|(p, _)| p == &path).any(|b| b) {
+                if self.imported.iter().any(|(p, _)| p == &path) {
                     return;
                 }
 
@@ -128,5 +128,27 @@ impl ImportHelper {
         for func in linker.get_public_funcs() {
             outer_linker.add_func(func, false)
         }
+        for structure in linker.get_public_structs() {
+            outer_linker.add_struct(structure, false)
+        }
+        for enumeration in linker.get_public_enums() {
+            outer_linker.add_enum(enumeration, false)
+        }
+        for union in linker.get_public_unions() {
+            outer_linker.add_union(union, false)
+        }
+        for alias in linker.get_public_aliases() {
+            outer_linker.add_alias(alias, false)
+        }
+        for global in linker.get_public_globals() {
+            outer_linker.add_global(global, false)
+        }
+        // Runtime routines and string literals are only emitted once, by the main file
+        for name in linker.get_runtime() {
+            outer_linker.use_runtime(name)
+        }
+        for (label, string) in linker.get_strings() {
+            outer_linker.add_string(label, string)
+        }
     }
 }
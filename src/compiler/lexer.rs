@@ -14,7 +14,7 @@ pub struct Token {
     pub end: usize,
 }
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     // Builtin datatypes
@@ -24,6 +24,7 @@ pub enum TokenType {
     Float,
     String,
     Character,
+    Boolean,
 
     // Other stuff
     Identifier,
@@ -32,6 +33,7 @@ pub enum TokenType {
     Flt,
     Str,
     Char,
+    Bool,
 
     OpenParen,
     CloseParen,
@@ -44,13 +46,43 @@ pub enum TokenType {
     Mult,
     Div,
     Mod,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    Shl,
+    Shr,
+
+    AddAssign,
+    SubAssign,
+    MultAssign,
+    DivAssign,
+    ModAssign,
+    AndAssign,
+    OrAssign,
+    XorAssign,
+    ShlAssign,
+    ShrAssign,
+    Inc,
+    Dec,
 
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
 
     If,
     Else,
     While,
+    For,
+    Do,
+    Break,
+    Continue,
+    Switch,
+    Case,
+    Default,
+    Match,
+    FatArrow,
 
     EQ,
     NEQ,
@@ -59,9 +91,25 @@ pub enum TokenType {
     LT,
     LTE,
 
+    And,
+    Or,
+    Not,
+
+    Struct,
+    Enum,
+    Union,
+    Typedef,
+    Type,
+    Const,
+    As,
+    Sizeof,
+    Alignof,
+
     Import,
     Dot,
     Colon,
+    DoubleColon,
+    Question,
 
     URCLBlock,
 
@@ -74,6 +122,24 @@ const UNSIGNED_INT_TYPES: [&str; 4] = ["uint8", "uint16", "uint32", "uint64"];
 
 const FLOAT_TYPES: [&str; 2] = ["float32", "float64"];
 
+// Checked before the single character operators, so longer operators have to come first
+const MULTI_CHAR_OPS: [(&str, TokenType); 14] = [
+    ("<<=", TokenType::ShlAssign),
+    (">>=", TokenType::ShrAssign),
+    ("+=", TokenType::AddAssign),
+    ("-=", TokenType::SubAssign),
+    ("*=", TokenType::MultAssign),
+    ("/=", TokenType::DivAssign),
+    ("%=", TokenType::ModAssign),
+    ("&=", TokenType::AndAssign),
+    ("|=", TokenType::OrAssign),
+    ("^=", TokenType::XorAssign),
+    ("++", TokenType::Inc),
+    ("--", TokenType::Dec),
+    ("::", TokenType::DoubleColon),
+    ("=>", TokenType::FatArrow),
+];
+
 pub fn tokenize(src: &String) -> Vec<Token> {
     let mut res = Vec::new();
     let mut buf = Buffer::new(src);
@@ -96,6 +162,19 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                 start: buf.line_pos(&lineno),
                 end: buf.line_pos(&lineno),
             })
+        } else if let Some((op, tok_type)) = MULTI_CHAR_OPS.iter().find(|(op, _)| buf.starts_with(op)) {
+            let start = buf.line_pos(&lineno);
+            for _ in 1..op.len() {
+                buf.advance()
+            }
+            res.push(Token {
+                lineno,
+                tok_type: tok_type.clone(),
+                val: op.to_string(),
+
+                start,
+                end: buf.line_pos(&lineno),
+            })
         } else if data == '=' {
             let start = buf.line_pos(&lineno);
             buf.advance();
@@ -114,9 +193,11 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::Assign,
                     val: data.to_string(),
 
-                    start: buf.line_pos(&lineno),
-                    end: buf.line_pos(&lineno),
-                })
+                    start,
+                    end: start,
+                });
+                // Already advanced past the operator
+                continue;
             }
         } else if data == '+' {
             res.push(Token {
@@ -160,6 +241,72 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                 tok_type: TokenType::Mod,
                 val: data.to_string(),
 
+                start: buf.line_pos(&lineno),
+                end: buf.line_pos(&lineno),
+            })
+        } else if data == '&' {
+            let start = buf.line_pos(&lineno);
+            buf.advance();
+            if buf.in_bounds() && buf.current("", &Default::default()) == '&' {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::And,
+                    val: "&&".to_string(),
+
+                    start,
+                    end: buf.line_pos(&lineno),
+                })
+            } else {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Ampersand,
+                    val: data.to_string(),
+
+                    start,
+                    end: start,
+                });
+                // Already advanced past the operator
+                continue;
+            }
+        } else if data == '|' {
+            let start = buf.line_pos(&lineno);
+            buf.advance();
+            if buf.in_bounds() && buf.current("", &Default::default()) == '|' {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Or,
+                    val: "||".to_string(),
+
+                    start,
+                    end: buf.line_pos(&lineno),
+                })
+            } else {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Pipe,
+                    val: data.to_string(),
+
+                    start,
+                    end: start,
+                });
+                // Already advanced past the operator
+                continue;
+            }
+        } else if data == '^' {
+            res.push(Token {
+                lineno,
+                tok_type: TokenType::Caret,
+                val: data.to_string(),
+
+                start: buf.line_pos(&lineno),
+                end: buf.line_pos(&lineno),
+            })
+        } else if data == '~' {
+            res.push(Token {
+                lineno,
+                tok_type: TokenType::Tilde,
+                val: data.to_string(),
+
                 start: buf.line_pos(&lineno),
                 end: buf.line_pos(&lineno),
             })
@@ -205,6 +352,24 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                 tok_type: TokenType::CloseBrace,
                 val: data.to_string(),
 
+                start: buf.line_pos(&lineno),
+                end: buf.line_pos(&lineno),
+            })
+        } else if data == '[' {
+            res.push(Token {
+                lineno,
+                tok_type: TokenType::OpenBracket,
+                val: data.to_string(),
+
+                start: buf.line_pos(&lineno),
+                end: buf.line_pos(&lineno),
+            })
+        } else if data == ']' {
+            res.push(Token {
+                lineno,
+                tok_type: TokenType::CloseBracket,
+                val: data.to_string(),
+
                 start: buf.line_pos(&lineno),
                 end: buf.line_pos(&lineno),
             })
@@ -217,6 +382,15 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::GTE,
                     val: ">=".to_string(),
 
+                    start,
+                    end: buf.line_pos(&lineno),
+                })
+            } else if buf.in_bounds() && buf.current("", &Default::default()) == '>' {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Shr,
+                    val: ">>".to_string(),
+
                     start,
                     end: buf.line_pos(&lineno),
                 })
@@ -226,9 +400,11 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::GT,
                     val: data.to_string(),
 
-                    start: buf.line_pos(&lineno),
-                    end: buf.line_pos(&lineno),
-                })
+                    start,
+                    end: start,
+                });
+                // Already advanced past the operator
+                continue;
             }
         } else if data == '<' {
             let start = buf.line_pos(&lineno);
@@ -239,6 +415,15 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::LTE,
                     val: "<=".to_string(),
 
+                    start,
+                    end: buf.line_pos(&lineno),
+                })
+            } else if buf.in_bounds() && buf.current("", &Default::default()) == '<' {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Shl,
+                    val: "<<".to_string(),
+
                     start,
                     end: buf.line_pos(&lineno),
                 })
@@ -248,9 +433,11 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::LT,
                     val: data.to_string(),
 
-                    start: buf.line_pos(&lineno),
-                    end: buf.line_pos(&lineno),
-                })
+                    start,
+                    end: start,
+                });
+                // Already advanced past the operator
+                continue;
             }
         } else if data == '!' {
             let start = buf.line_pos(&lineno);
@@ -265,42 +452,31 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     end: buf.line_pos(&lineno),
                 })
             } else {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Not,
+                    val: data.to_string(),
+
+                    start,
+                    end: start,
+                });
+                // Already advanced past the operator
                 continue;
             }
         } else if data == '\'' {
             let start = buf.line_pos(&lineno);
             buf.advance();
-            let _char;
 
             if !buf.in_bounds() {
                 print_error("Expected character after ' at line {}", src, start, buf.line_pos(&lineno), lineno);
                 exit(1)
             }
-            if buf.current("", &Default::default()) == '\\' {
+            let _char = if buf.current("", &Default::default()) == '\\' {
                 buf.advance();
-
-                match buf.current(
-                    "Expected escape character after \\",
-                    &PosInfo {
-                        src: src.to_string(),
-                        start,
-                        end: buf.line_pos(&lineno),
-                        lineno,
-                    },
-                ) {
-                    'n' => _char = '\n',
-                    't' => _char = '\t',
-                    '\'' => _char = '\'',
-                    '\\' => _char = '\\',
-
-                    _ => {
-                        print_error("Invalid escape character at line {}", src, start, buf.line_pos(&lineno), lineno);
-                        exit(1)
-                    }
-                }
+                lex_escape(&mut buf, src, start, lineno)
             } else {
-                _char = buf.current("", &Default::default())
-            }
+                buf.current("", &Default::default())
+            };
 
             if buf.next(
                 "Expected closing ' for character literal",
@@ -332,26 +508,7 @@ pub fn tokenize(src: &String) -> Vec<Token> {
             while buf.current("", &Default::default()) != '"' {
                 if buf.current("", &Default::default()) == '\\' {
                     buf.advance();
-
-                    match buf.current(
-                        "Expected escape character after \\",
-                        &PosInfo {
-                            src: src.to_string(),
-                            start,
-                            end: buf.line_pos(&lineno),
-                            lineno,
-                        },
-                    ) {
-                        'n' => _str += "\n",
-                        't' => _str += "\t",
-                        '\'' => _str += "\'",
-                        '\\' => _str += "\\",
-
-                        _ => {
-                            print_error("Invalid escape character at line {}", src, start, buf.line_pos(&lineno), lineno);
-                            exit(1)
-                        }
-                    }
+                    _str.push(lex_escape(&mut buf, src, start, lineno))
                 } else if buf.current("", &Default::default()) == '\n' {
                     print_error("Unterminated string at line {}", src, start, buf.line_pos(&lineno), lineno);
                     exit(1)
@@ -385,6 +542,15 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                 tok_type: TokenType::Colon,
                 val: data.to_string(),
 
+                start: buf.line_pos(&lineno),
+                end: buf.line_pos(&lineno),
+            })
+        } else if data == '?' {
+            res.push(Token {
+                lineno,
+                tok_type: TokenType::Question,
+                val: data.to_string(),
+
                 start: buf.line_pos(&lineno),
                 end: buf.line_pos(&lineno),
             })
@@ -457,6 +623,24 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::Character,
                     val: word,
 
+                    start,
+                    end,
+                })
+            } else if word == "bool" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Boolean,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "true" || word == "false" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Bool,
+                    val: word,
+
                     start,
                     end,
                 })
@@ -484,6 +668,159 @@ pub fn tokenize(src: &String) -> Vec<Token> {
                     tok_type: TokenType::While,
                     val: word,
 
+                    start,
+                    end,
+                })
+            } else if word == "for" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::For,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "do" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Do,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "break" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Break,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "continue" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Continue,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "switch" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Switch,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "case" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Case,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "default" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Default,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "match" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Match,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "struct" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Struct,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "enum" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Enum,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "union" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Union,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "typedef" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Typedef,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "type" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Type,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "const" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Const,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "as" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::As,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "sizeof" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Sizeof,
+                    val: word,
+
+                    start,
+                    end,
+                })
+            } else if word == "alignof" {
+                res.push(Token {
+                    lineno,
+                    tok_type: TokenType::Alignof,
+                    val: word,
+
                     start,
                     end,
                 })
@@ -528,12 +865,16 @@ pub fn tokenize(src: &String) -> Vec<Token> {
             continue;
         } else if data.is_numeric() {
             let mut num = String::new();
+            let mut tok_type = TokenType::Num;
             let start = buf.line_pos(&lineno);
 
             while buf.in_bounds() {
                 let curr = buf.current("", &Default::default());
 
-                if curr == ';' || !curr.is_numeric() {
+                // A single '.' followed by a digit makes it a float literal
+                if curr == '.' && tok_type == TokenType::Num && buf.peek().is_some_and(|next| next.is_ascii_digit()) {
+                    tok_type = TokenType::Flt
+                } else if curr == ';' || !curr.is_numeric() {
                     break;
                 }
 
@@ -545,7 +886,7 @@ pub fn tokenize(src: &String) -> Vec<Token> {
 
             res.push(Token {
                 lineno,
-                tok_type: TokenType::Num,
+                tok_type,
                 val: num,
 
                 start,
@@ -561,6 +902,49 @@ pub fn tokenize(src: &String) -> Vec<Token> {
     res
 }
 
+// Reads the escape sequence after a '\\', leaving the buffer on its last character
+fn lex_escape(buf: &mut Buffer, src: &str, start: usize, lineno: usize) -> char {
+    let pos = PosInfo {
+        src: src.to_string(),
+        start,
+        end: buf.line_pos(&lineno),
+        lineno,
+    };
+
+    match buf.current("Expected escape character after \\", &pos) {
+        'n' => '\n',
+        't' => '\t',
+        '0' => '\0',
+        '\'' => '\'',
+        '"' => '"',
+        '\\' => '\\',
+        'x' => {
+            let hex = [
+                buf.next("Expected hex digit after \\x", &pos),
+                buf.next("Expected hex digit after \\x", &pos),
+            ];
+            match u8::from_str_radix(&String::from_iter(hex), 16) {
+                Ok(val) => val as char,
+                Err(_) => {
+                    print_error(
+                        "Invalid hex escape, expected \\x followed by two hex digits",
+                        src,
+                        start,
+                        buf.line_pos(&lineno),
+                        lineno,
+                    );
+                    exit(1)
+                }
+            }
+        }
+
+        _ => {
+            print_error("Invalid escape character", src, start, buf.line_pos(&lineno), lineno);
+            exit(1)
+        }
+    }
+}
+
 struct Buffer {
     data: String,
     index: usize,
@@ -587,6 +971,14 @@ impl Buffer {
         self.current(err, pos)
     }
 
+    pub fn starts_with(&self, s: &str) -> bool {
+        self.data.chars().skip(self.index).take(s.len()).eq(s.chars())
+    }
+
+    pub fn peek(&self) -> Option<char> {
+        self.data.chars().nth(self.index + 1)
+    }
+
     pub fn current(&self, err: &str, pos: &PosInfo) -> char {
         unwrap_or_err!(self.data.chars().nth(self.index), (pos.src, pos.start, pos.end, pos.lineno, err))
     }
@@ -1,15 +1,40 @@
 use std::process::exit;
 
+use super::ast::conversions::{implicit_conversion, Rank};
 use super::ast::nodes::Type;
+use super::runtime::get_runtime_deps;
 
 #[derive(Debug, Clone)]
 pub struct Linker {
     // format: (function, is_public)
     funcs: Vec<(LinkerFunc, bool)>,
+    // format: (struct, is_public)
+    structs: Vec<(LinkerStruct, bool)>,
+    // format: (enum, is_public)
+    enums: Vec<(LinkerEnum, bool)>,
+    // format: (union, is_public)
+    unions: Vec<(LinkerUnion, bool)>,
+    // format: (alias, is_public)
+    aliases: Vec<(LinkerAlias, bool)>,
+    // format: (global, is_public)
+    globals: Vec<(LinkerGlobal, bool)>,
+    // Runtime routines called by the compiled code
+    runtime: Vec<String>,
+    // String literals of imported modules, format: (label, string)
+    strings: Vec<(String, String)>,
 }
 impl Linker {
     pub fn new() -> Linker {
-        Linker { funcs: Vec::new() }
+        Linker {
+            funcs: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+            unions: Vec::new(),
+            aliases: Vec::new(),
+            globals: Vec::new(),
+            runtime: Vec::new(),
+            strings: Vec::new(),
+        }
     }
 
     pub fn add_func(&mut self, function: &LinkerFunc, public: bool) {
@@ -31,54 +56,259 @@ impl Linker {
         self.funcs.push((function.clone(), public))
     }
 
+    // The candidate whose worst argument conversion is the best wins, ties go to fewer conversions and then to the earlier definition
     pub fn get_func(&self, name: &String, arg_types: &[Type]) -> Option<LinkerFunc> {
-        fn transform_arg_type(arg_type: &Type) -> String {
-            match arg_type {
-                Type::Named(name) => match name.as_str() {
-                    "void" => String::from("void"),
-                    "int8" => String::from("int"),
-                    "int16" => String::from("int"),
-                    "int32" => String::from("int"),
-                    "int64" => String::from("int"),
+        self.funcs
+            .iter()
+            .filter(|(func, _)| func.name == *name && func.arg_types.len() == arg_types.len())
+            .filter_map(|(func, _)| {
+                let conversions = func
+                    .arg_types
+                    .iter()
+                    .zip(arg_types)
+                    .map(|(param, arg)| implicit_conversion(arg, param))
+                    .collect::<Option<Vec<_>>>()?;
+                let worst = conversions.iter().max().copied().unwrap_or(Rank::Exact);
+                let inexact = conversions.iter().filter(|conversion| **conversion != Rank::Exact).count();
+                Some(((worst, inexact), func))
+            })
+            .min_by_key(|(rank, _)| *rank)
+            .map(|(_, func)| func.clone())
+    }
 
-                    "uint8" => String::from("int"),
-                    "uint16" => String::from("int"),
-                    "uint32" => String::from("int"),
-                    "uint64" => String::from("int"),
+    pub fn set_func_code(&mut self, signature: &str, code: &str) {
+        for (func, _) in &mut self.funcs {
+            if func.get_signature() == signature {
+                func.code = code.to_owned()
+            }
+        }
+    }
 
-                    "int" => String::from("int"),
+    pub fn get_funcs(&self) -> Vec<&LinkerFunc> {
+        self.funcs.iter().map(|(f, _)| f).collect()
+    }
 
-                    "float32" => String::from("f32"),
-                    "float64" => String::from("f64"),
+    pub fn get_public_funcs(&self) -> Vec<&LinkerFunc> {
+        self.funcs.iter().filter(|(_, public)| *public).map(|(f, _)| f).collect()
+    }
 
-                    "string" => String::from("string"),
-                    "char" => String::from("char"),
+    pub fn add_struct(&mut self, structure: &LinkerStruct, public: bool) {
+        if self.is_type_defined(&structure.name) {
+            eprintln!("Error: Duplicate struct {}", structure.name);
+            exit(1)
+        }
 
-                    _ => unreachable!(),
-                },
+        self.structs.push((structure.clone(), public))
+    }
 
-                Type::Ptr(typ) => format!("{}*", transform_arg_type(typ)),
-                Type::Arr(typ) => format!("{}[]", transform_arg_type(typ)),
-                Type::Const(typ) => format!("{}-const", transform_arg_type(typ)),
-            }
+    pub fn get_struct(&self, name: &str) -> Option<&LinkerStruct> {
+        self.structs.iter().map(|(s, _)| s).find(|s| s.name == name)
+    }
+
+    pub fn get_public_structs(&self) -> Vec<&LinkerStruct> {
+        self.structs.iter().filter(|(_, public)| *public).map(|(s, _)| s).collect()
+    }
+
+    pub fn add_enum(&mut self, enumeration: &LinkerEnum, public: bool) {
+        if self.is_type_defined(&enumeration.name) {
+            eprintln!("Error: Duplicate type {}", enumeration.name);
+            exit(1)
         }
 
-        for (func, _) in &self.funcs {
-            let func_arg_types = func.arg_types.iter().map(transform_arg_type).collect::<Vec<_>>();
-            let arg_types = arg_types.iter().map(transform_arg_type).collect::<Vec<_>>();
-            if func.name == *name && func_arg_types == arg_types {
-                return Some(func.clone());
+        self.enums.push((enumeration.clone(), public))
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&LinkerEnum> {
+        self.enums.iter().map(|(e, _)| e).find(|e| e.name == name)
+    }
+
+    pub fn get_public_enums(&self) -> Vec<&LinkerEnum> {
+        self.enums.iter().filter(|(_, public)| *public).map(|(e, _)| e).collect()
+    }
+
+    pub fn add_union(&mut self, union: &LinkerUnion, public: bool) {
+        if self.is_type_defined(&union.name) {
+            eprintln!("Error: Duplicate type {}", union.name);
+            exit(1)
+        }
+
+        self.unions.push((union.clone(), public))
+    }
+
+    pub fn get_union(&self, name: &str) -> Option<&LinkerUnion> {
+        self.unions.iter().map(|(u, _)| u).find(|u| u.name == name)
+    }
+
+    pub fn get_public_unions(&self) -> Vec<&LinkerUnion> {
+        self.unions.iter().filter(|(_, public)| *public).map(|(u, _)| u).collect()
+    }
+
+    pub fn add_alias(&mut self, alias: &LinkerAlias, public: bool) {
+        if self.is_type_defined(&alias.name) {
+            eprintln!("Error: Duplicate type {}", alias.name);
+            exit(1)
+        }
+
+        self.aliases.push((alias.clone(), public))
+    }
+
+    pub fn get_alias(&self, name: &str) -> Option<&LinkerAlias> {
+        self.aliases.iter().map(|(a, _)| a).find(|a| a.name == name)
+    }
+
+    pub fn get_public_aliases(&self) -> Vec<&LinkerAlias> {
+        self.aliases.iter().filter(|(_, public)| *public).map(|(a, _)| a).collect()
+    }
+
+    fn is_type_defined(&self, name: &str) -> bool {
+        self.get_struct(name).is_some() || self.get_enum(name).is_some() || self.get_union(name).is_some() || self.get_alias(name).is_some()
+    }
+
+    pub fn add_global(&mut self, global: &LinkerGlobal, public: bool) {
+        if self.get_global(&global.name).is_some() {
+            eprintln!("Error: Duplicate global {}", global.name);
+            exit(1)
+        }
+
+        self.globals.push((global.clone(), public))
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&LinkerGlobal> {
+        self.globals.iter().map(|(g, _)| g).find(|g| g.name == name)
+    }
+
+    pub fn get_globals(&self) -> Vec<&LinkerGlobal> {
+        self.globals.iter().map(|(g, _)| g).collect()
+    }
+
+    pub fn get_public_globals(&self) -> Vec<&LinkerGlobal> {
+        self.globals.iter().filter(|(_, public)| *public).map(|(g, _)| g).collect()
+    }
+
+    pub fn use_runtime(&mut self, name: &str) {
+        if !self.runtime.iter().any(|used| used == name) {
+            self.runtime.push(name.to_owned());
+            get_runtime_deps(name).iter().for_each(|dep| self.use_runtime(dep))
+        }
+    }
+
+    pub fn get_runtime(&self) -> &[String] {
+        &self.runtime
+    }
+
+    pub fn add_string(&mut self, label: &str, string: &str) {
+        self.strings.push((label.to_owned(), string.to_owned()))
+    }
+
+    pub fn get_strings(&self) -> &[(String, String)] {
+        &self.strings
+    }
+
+    // Size of a type in words
+    pub fn size_of(&self, typ: &Type) -> u64 {
+        match typ {
+            Type::Named(name) => match name.as_str() {
+                _ if typ.builtin_size().is_some() => typ.builtin_size().unwrap(),
+
+                // Enums are stored as their int32 value
+                _ if self.get_enum(name).is_some() => 1,
+                // The tag, followed by room for the largest variant
+                _ if self.get_union(name).is_some() => {
+                    let union = self.get_union(name).unwrap();
+                    let payload = union
+                        .variants
+                        .iter()
+                        .map(|(_, fields)| fields.iter().map(|(typ, _)| self.size_of(typ)).sum::<u64>())
+                        .max()
+                        .unwrap_or(0);
+                    1 + payload
+                }
+                _ => match self.get_struct(name) {
+                    Some(structure) => structure.fields.iter().map(|(typ, _)| self.size_of(typ)).sum(),
+                    None => {
+                        eprintln!("Error: Unknown type {}", name);
+                        exit(1)
+                    }
+                },
+            },
+            Type::Ptr(_) => 1,
+            Type::Arr(typ, Some(len)) => len * self.size_of(typ),
+            // Unsized arrays only exist as arguments, which are passed as pointers
+            Type::Arr(_, None) => 1,
+            Type::Const(typ) | Type::Alias(_, typ) => self.size_of(typ),
+            Type::ArrExpr(..) => unreachable!("array lengths are evaluated when the type is resolved"),
+        }
+    }
+
+    // Type and word offset of a struct field
+    pub fn get_field(&self, struct_name: &str, field: &str) -> Option<(Type, u64)> {
+        let structure = self.get_struct(struct_name)?;
+        let mut offset = 0;
+        for (typ, name) in &structure.fields {
+            if name == field {
+                return Some((typ.clone(), offset));
             }
+            offset += self.size_of(typ)
         }
         None
     }
+}
 
-    pub fn get_funcs(&self) -> Vec<&LinkerFunc> {
-        self.funcs.iter().map(|(f, _)| f).collect()
+#[derive(Debug, Clone)]
+pub struct LinkerStruct {
+    pub name: String,
+    pub fields: Vec<(Type, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkerEnum {
+    pub name: String,
+    pub variants: Vec<(String, i64)>,
+}
+impl LinkerEnum {
+    pub fn get_value(&self, variant: &str) -> Option<i64> {
+        self.variants.iter().find(|(name, _)| name == variant).map(|(_, value)| *value)
     }
+}
 
-    pub fn get_public_funcs(&self) -> Vec<&LinkerFunc> {
-        self.funcs.iter().filter(|(_, public)| *public).map(|(f, _)| f).collect()
+#[derive(Debug, Clone)]
+pub struct LinkerAlias {
+    pub name: String,
+    // Already resolved, so aliases of aliases stand for the final type
+    pub typ: Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkerUnion {
+    pub name: String,
+    // The tag of a variant is its index
+    pub variants: Vec<(String, Vec<(Type, String)>)>,
+}
+impl LinkerUnion {
+    // Tag and fields of a variant
+    pub fn get_variant(&self, variant: &str) -> Option<(u64, &[(Type, String)])> {
+        self.variants
+            .iter()
+            .position(|(name, _)| name == variant)
+            .map(|tag| (tag as u64, self.variants[tag].1.as_slice()))
+    }
+}
+
+// A top level variable, stored as data after the code
+#[derive(Debug, Clone)]
+pub struct LinkerGlobal {
+    pub name: String,
+    pub typ: Type,
+    // Initial value, one `DW` operand per word
+    pub words: Vec<String>,
+    // Characters of the string initializers, the words refer to them by `{label}_str{idx}`
+    pub strings: Vec<String>,
+    // Value of integer constants, so they can be used where the compiler needs a number
+    pub value: Option<i64>,
+}
+impl LinkerGlobal {
+    pub fn get_label(&self) -> String {
+        format!(".global_{}", self.name)
     }
 }
 
@@ -99,35 +329,43 @@ impl LinkerFunc {
         }
     }
 
+    pub fn get_ret_type(&self) -> &Type {
+        &self.ret_type
+    }
+
+    pub fn get_arg_types(&self) -> &[Type] {
+        &self.arg_types
+    }
+
     pub fn get_signature(&self) -> String {
         let mut s = String::new();
 
         s += format!("_Hx{}{}", self.name.len(), self.name).as_str();
 
-        let (ret_len, ret) = LinkerFunc::encode_type(&self.ret_type);
-        if ret_len == usize::MAX {
-            s += ret.as_str();
-        } else {
-            s += format!("_{}{}", ret_len, ret).as_str()
-        }
-
+        s += LinkerFunc::mangle_type(&self.ret_type).as_str();
         for arg in &self.arg_types {
-            let (var_len, var) = LinkerFunc::encode_type(arg);
-            if var_len == usize::MAX {
-                s += var.as_str();
-            } else {
-                s += format!("_{}{}", var_len, var).as_str()
-            }
+            s += LinkerFunc::mangle_type(arg).as_str()
         }
 
         s
     }
 
+    fn mangle_type(typ: &Type) -> String {
+        let (len, s) = LinkerFunc::encode_type(typ);
+        if len == usize::MAX {
+            s
+        } else {
+            format!("_{}{}", len, s)
+        }
+    }
+
     fn encode_type(typ: &Type) -> (usize, String) {
         let s;
         let mut is_ident = false;
 
         let len = match typ {
+            // Aliases are mangled as the type they stand for, so they overload the same way
+            Type::Alias(_, typ) => return LinkerFunc::encode_type(typ),
             Type::Named(name) => {
                 match name.as_str() {
                     "void" => s = String::from("v"),
@@ -146,6 +384,7 @@ impl LinkerFunc {
 
                     "string" => s = String::from("s"),
                     "char" => s = String::from("c"),
+                    "bool" => s = String::from("b"),
 
                     _ => {
                         s = name.clone();
@@ -155,9 +394,19 @@ impl LinkerFunc {
 
                 name.len()
             }
-            Type::Ptr(_) => todo!(),
-            Type::Arr(_) => todo!(),
-            Type::Const(_) => todo!(),
+            Type::Ptr(typ) => {
+                s = format!("P{}", LinkerFunc::mangle_type(typ));
+                0
+            }
+            // Array lengths are not part of the signature as arrays are passed as pointers
+            Type::Arr(typ, _) | Type::ArrExpr(typ, ..) => {
+                s = format!("A{}", LinkerFunc::mangle_type(typ));
+                0
+            }
+            Type::Const(typ) => {
+                s = format!("K{}", LinkerFunc::mangle_type(typ));
+                0
+            }
         };
 
         (
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod compiler;
 pub use compiler::*;
 
@@ -6,6 +7,7 @@ pub mod ast;
 pub mod imports;
 pub mod lexer;
 pub mod linker;
+pub mod runtime;
 pub mod strings;
 
 pub fn print_error(err: &str, src: &str, start: usize, end: usize, lineno: usize) {
@@ -0,0 +1,727 @@
+// Routines for 64 bit integer and float64 operations that have no single instruction on a 32 bit target.
+// They are called with the operands on the stack, pushed high word first like every other multi word value,
+// and return the low word in R2 and the high word in R3. Only R2 to R8 are clobbered.
+//
+// Float64s are IEEE 754 doubles with the sign, exponent and top of the mantissa in the high word.
+// Subnormal numbers are treated as zero, NaNs and infinities are mostly just passed on.
+
+// Shift and add, one bit of the multiplier at a time
+const MUL64: &str = "\
+.rt_mul64
+LLOD R4 SP 3
+LLOD R5 SP 4
+LLOD R6 SP 1
+LLOD R7 SP 2
+IMM R2 0
+IMM R3 0
+.rt_mul64_loop
+AND R8 R6 1
+BRZ .rt_mul64_shift R8
+ADD R2 R2 R4
+SETL R8 R2 R4
+ADD R3 R3 R5
+SUB R3 R3 R8
+.rt_mul64_shift
+BSL R5 R5 1
+BRP .rt_mul64_no_carry R4
+INC R5 R5
+.rt_mul64_no_carry
+BSL R4 R4 1
+BSR R6 R6 1
+BSL R8 R7 31
+OR R6 R6 R8
+BSR R7 R7 1
+BNZ .rt_mul64_loop R6
+BNZ .rt_mul64_loop R7
+RET
+";
+
+// Restoring division, also leaves the remainder in R4 (low) and R5 (high)
+const UDIVMOD64: &str = "\
+.rt_udivmod64
+LLOD R2 SP 3
+LLOD R3 SP 4
+LLOD R6 SP 1
+LLOD R7 SP 2
+IMM R4 0
+IMM R5 0
+PSH 64
+.rt_udivmod64_loop
+BSR R8 R5 31
+BSL R5 R5 1
+BRP .rt_udivmod64_shift1 R4
+INC R5 R5
+.rt_udivmod64_shift1
+BSL R4 R4 1
+BRP .rt_udivmod64_shift2 R3
+INC R4 R4
+.rt_udivmod64_shift2
+BSL R3 R3 1
+BRP .rt_udivmod64_shift3 R2
+INC R3 R3
+.rt_udivmod64_shift3
+BSL R2 R2 1
+BNZ .rt_udivmod64_sub R8
+BRL .rt_udivmod64_next R5 R7
+BRG .rt_udivmod64_sub R5 R7
+BRL .rt_udivmod64_next R4 R6
+.rt_udivmod64_sub
+BGE .rt_udivmod64_no_borrow R4 R6
+DEC R5 R5
+.rt_udivmod64_no_borrow
+SUB R4 R4 R6
+SUB R5 R5 R7
+OR R2 R2 1
+.rt_udivmod64_next
+LLOD R8 SP 0
+DEC R8 R8
+LSTR SP 0 R8
+BNZ .rt_udivmod64_loop R8
+INC SP SP
+RET
+";
+
+// Divides the magnitudes, the quotient is negative when the signs differ and the remainder takes the sign of the dividend
+const SDIVMOD64: &str = "\
+.rt_sdivmod64
+LLOD R2 SP 3
+LLOD R3 SP 4
+LLOD R4 SP 1
+LLOD R5 SP 2
+BSS R6 R3 31
+BSS R7 R5 31
+XOR R7 R7 R6
+PSH R6
+PSH R7
+BRP .rt_sdivmod64_dividend R3
+NOT R2 R2
+NOT R3 R3
+INC R2 R2
+BNZ .rt_sdivmod64_dividend R2
+INC R3 R3
+.rt_sdivmod64_dividend
+BRP .rt_sdivmod64_divisor R5
+NOT R4 R4
+NOT R5 R5
+INC R4 R4
+BNZ .rt_sdivmod64_divisor R4
+INC R5 R5
+.rt_sdivmod64_divisor
+PSH R3
+PSH R2
+PSH R5
+PSH R4
+CAL .rt_udivmod64
+ADD SP SP 4
+POP R6
+BRZ .rt_sdivmod64_quotient R6
+NOT R2 R2
+NOT R3 R3
+INC R2 R2
+BNZ .rt_sdivmod64_quotient R2
+INC R3 R3
+.rt_sdivmod64_quotient
+POP R6
+BRZ .rt_sdivmod64_remainder R6
+NOT R4 R4
+NOT R5 R5
+INC R4 R4
+BNZ .rt_sdivmod64_remainder R4
+INC R5 R5
+.rt_sdivmod64_remainder
+RET
+";
+
+// Shifts take the value and then a single word amount
+const SHL64: &str = "\
+.rt_shl64
+LLOD R2 SP 2
+LLOD R3 SP 3
+LLOD R4 SP 1
+AND R4 R4 63
+BRZ .rt_shl64_end R4
+.rt_shl64_loop
+BSL R3 R3 1
+BRP .rt_shl64_no_carry R2
+INC R3 R3
+.rt_shl64_no_carry
+BSL R2 R2 1
+DEC R4 R4
+BNZ .rt_shl64_loop R4
+.rt_shl64_end
+RET
+";
+
+const SHR64: &str = "\
+.rt_shr64
+LLOD R2 SP 2
+LLOD R3 SP 3
+LLOD R4 SP 1
+AND R4 R4 63
+BRZ .rt_shr64_end R4
+.rt_shr64_loop
+BSR R2 R2 1
+BSL R5 R3 31
+OR R2 R2 R5
+BSR R3 R3 1
+DEC R4 R4
+BNZ .rt_shr64_loop R4
+.rt_shr64_end
+RET
+";
+
+// Like `rt_shr64`, but keeps the sign
+const SAR64: &str = "\
+.rt_sar64
+LLOD R2 SP 2
+LLOD R3 SP 3
+LLOD R4 SP 1
+AND R4 R4 63
+BRZ .rt_sar64_end R4
+.rt_sar64_loop
+BSR R2 R2 1
+BSL R5 R3 31
+OR R2 R2 R5
+BSS R3 R3 1
+DEC R4 R4
+BNZ .rt_sar64_loop R4
+.rt_sar64_end
+RET
+";
+
+// Rounds and packs a float64 for the other float64 routines, it is not called with its operands on the stack.
+// Takes the mantissa in R2 (low) and R3 (high) with its leading bit anywhere, the biased exponent it would have
+// if that bit was bit 55 in R6 and the sign bit in R4. Rounds to nearest even, results too small for a normal
+// float64 become zero and ones too large infinity.
+const FPACK64: &str = "\
+.rt_fpack64
+OR R7 R2 R3
+BRZ .rt_fpack64_zero R7
+.rt_fpack64_right
+BSR R7 R3 24
+BRZ .rt_fpack64_left R7
+AND R7 R2 1
+BSR R2 R2 1
+OR R2 R2 R7
+BSL R7 R3 31
+OR R2 R2 R7
+BSR R3 R3 1
+INC R6 R6
+JMP .rt_fpack64_right
+.rt_fpack64_left
+BSR R7 R3 23
+BNZ .rt_fpack64_round R7
+BSL R3 R3 1
+BSR R7 R2 31
+OR R3 R3 R7
+BSL R2 R2 1
+DEC R6 R6
+JMP .rt_fpack64_left
+.rt_fpack64_round
+AND R7 R2 7
+BSR R2 R2 3
+BSL R8 R3 29
+OR R2 R2 R8
+BSR R3 R3 3
+BRL .rt_fpack64_exponent R7 4
+BRG .rt_fpack64_up R7 4
+AND R8 R2 1
+BRZ .rt_fpack64_exponent R8
+.rt_fpack64_up
+INC R2 R2
+BNZ .rt_fpack64_no_carry R2
+INC R3 R3
+.rt_fpack64_no_carry
+BSR R8 R3 21
+BRZ .rt_fpack64_exponent R8
+BSR R3 R3 1
+INC R6 R6
+.rt_fpack64_exponent
+SBLE .rt_fpack64_zero R6 0
+SBGE .rt_fpack64_inf R6 0x7ff
+AND R3 R3 0xfffff
+BSL R6 R6 20
+OR R3 R3 R6
+OR R3 R3 R4
+RET
+.rt_fpack64_zero
+IMM R2 0
+MOV R3 R4
+RET
+.rt_fpack64_inf
+IMM R2 0
+OR R3 R4 0x7ff00000
+RET
+";
+
+// Adds the magnitudes or subtracts the smaller one from the larger one, keeping three extra bits for rounding.
+// Bits shifted out of the smaller operand are collected in the lowest bit, so that rounding sees them.
+const FADD64: &str = "\
+.rt_fadd64
+LLOD R2 SP 3
+LLOD R3 SP 4
+LLOD R4 SP 1
+LLOD R5 SP 2
+AND R7 R3 0x7fffffff
+AND R8 R5 0x7fffffff
+BRG .rt_fadd64_ordered R7 R8
+BRL .rt_fadd64_swap R7 R8
+BGE .rt_fadd64_ordered R2 R4
+.rt_fadd64_swap
+LSTR SP 1 R2
+LSTR SP 2 R3
+LSTR SP 3 R4
+LSTR SP 4 R5
+MOV R2 R4
+MOV R3 R5
+LLOD R4 SP 1
+LLOD R5 SP 2
+.rt_fadd64_ordered
+BSR R6 R3 20
+AND R6 R6 0x7ff
+BSR R7 R5 20
+AND R7 R7 0x7ff
+BNE .rt_fadd64_finite R6 0x7ff
+BNE .rt_fadd64_end R7 0x7ff
+XOR R8 R3 R5
+BRP .rt_fadd64_end R8
+IMM R2 0
+IMM R3 0x7ff80000
+RET
+.rt_fadd64_finite
+BNZ .rt_fadd64_nonzero R7
+BNZ .rt_fadd64_end R6
+AND R3 R3 R5
+AND R3 R3 0x80000000
+IMM R2 0
+RET
+.rt_fadd64_nonzero
+AND R3 R3 0xfffff
+OR R3 R3 0x100000
+AND R5 R5 0xfffff
+OR R5 R5 0x100000
+BSL R3 R3 3
+BSR R8 R2 29
+OR R3 R3 R8
+BSL R2 R2 3
+BSL R5 R5 3
+BSR R8 R4 29
+OR R5 R5 R8
+BSL R4 R4 3
+SUB R7 R6 R7
+BRZ .rt_fadd64_aligned R7
+BRL .rt_fadd64_shift R7 56
+IMM R4 1
+IMM R5 0
+JMP .rt_fadd64_aligned
+.rt_fadd64_shift
+IMM R8 0
+BRL .rt_fadd64_bits R7 32
+SETNE R8 R4 0
+MOV R4 R5
+IMM R5 0
+SUB R7 R7 32
+BNZ .rt_fadd64_bits R7
+AND R8 R8 1
+OR R4 R4 R8
+JMP .rt_fadd64_aligned
+.rt_fadd64_bits
+PSH R6
+PSH R8
+NEG R8 R7
+ADD R8 R8 32
+BSL R6 R4 R8
+SETNE R6 R6 0
+BSR R4 R4 R7
+BSL R8 R5 R8
+OR R4 R4 R8
+BSR R5 R5 R7
+POP R7
+OR R6 R6 R7
+AND R6 R6 1
+OR R4 R4 R6
+POP R6
+.rt_fadd64_aligned
+LLOD R7 SP 2
+LLOD R8 SP 4
+XOR R7 R7 R8
+BRN .rt_fadd64_sub R7
+ADD R2 R2 R4
+SETL R7 R2 R4
+ADD R3 R3 R5
+SUB R3 R3 R7
+JMP .rt_fadd64_pack
+.rt_fadd64_sub
+SETL R7 R2 R4
+SUB R2 R2 R4
+SUB R3 R3 R5
+ADD R3 R3 R7
+OR R7 R2 R3
+BNZ .rt_fadd64_pack R7
+IMM R3 0
+RET
+.rt_fadd64_pack
+AND R4 R8 0x80000000
+CAL .rt_fpack64
+.rt_fadd64_end
+RET
+";
+
+// Flips the sign of the right operand and adds
+const FSUB64: &str = "\
+.rt_fsub64
+LLOD R2 SP 2
+XOR R2 R2 0x80000000
+LSTR SP 2 R2
+JMP .rt_fadd64
+";
+
+// Shift and add like `rt_mul64`, but the product is shifted right instead of the multiplicand so that it fits into
+// two words. A sentinel bit above the multiplier ends the loop after its 53 bits.
+const FMUL64: &str = "\
+.rt_fmul64
+LLOD R3 SP 4
+LLOD R5 SP 2
+XOR R4 R3 R5
+AND R4 R4 0x80000000
+BSR R6 R3 20
+AND R6 R6 0x7ff
+BSR R7 R5 20
+AND R7 R7 0x7ff
+BRE .rt_fmul64_lhs R6 0x7ff
+BRE .rt_fmul64_rhs R7 0x7ff
+BRZ .rt_fmul64_zero R6
+BRZ .rt_fmul64_zero R7
+PSH R4
+ADD R6 R6 R7
+SUB R6 R6 1023
+PSH R6
+LLOD R4 SP 5
+LLOD R6 SP 3
+AND R3 R3 0xfffff
+OR R3 R3 0x100000
+BSL R5 R3 4
+BSR R8 R4 28
+OR R5 R5 R8
+BSL R4 R4 4
+LLOD R7 SP 4
+AND R7 R7 0xfffff
+OR R7 R7 0x300000
+IMM R2 0
+IMM R3 0
+.rt_fmul64_loop
+AND R8 R6 1
+BRZ .rt_fmul64_shift R8
+ADD R2 R2 R4
+SETL R8 R2 R4
+ADD R3 R3 R5
+SUB R3 R3 R8
+.rt_fmul64_shift
+AND R8 R2 1
+BSR R2 R2 1
+OR R2 R2 R8
+BSL R8 R3 31
+OR R2 R2 R8
+BSR R3 R3 1
+BSR R6 R6 1
+BSL R8 R7 31
+OR R6 R6 R8
+BSR R7 R7 1
+BNZ .rt_fmul64_loop R7
+BNE .rt_fmul64_loop R6 1
+POP R6
+POP R4
+CAL .rt_fpack64
+RET
+.rt_fmul64_lhs
+LLOD R2 SP 3
+AND R3 R3 0x7fffffff
+OR R3 R3 R4
+RET
+.rt_fmul64_rhs
+LLOD R2 SP 1
+AND R3 R5 0x7fffffff
+OR R3 R3 R4
+RET
+.rt_fmul64_zero
+IMM R2 0
+MOV R3 R4
+RET
+";
+
+// Restoring division of the mantissas, the dividend is doubled first when it is the smaller one.
+// Stops once the sentinel bit in the quotient reaches bit 56, the remainder makes up the lowest bit.
+const FDIV64: &str = "\
+.rt_fdiv64
+LLOD R3 SP 4
+LLOD R5 SP 2
+XOR R4 R3 R5
+AND R4 R4 0x80000000
+BSR R6 R3 20
+AND R6 R6 0x7ff
+BSR R7 R5 20
+AND R7 R7 0x7ff
+BRE .rt_fdiv64_lhs R6 0x7ff
+BRE .rt_fdiv64_zero R7 0x7ff
+BRZ .rt_fdiv64_by_zero R7
+BRZ .rt_fdiv64_zero R6
+PSH R4
+SUB R6 R6 R7
+ADD R6 R6 1023
+PSH R6
+LLOD R2 SP 5
+AND R3 R3 0xfffff
+OR R3 R3 0x100000
+LLOD R4 SP 3
+AND R5 R5 0xfffff
+OR R5 R5 0x100000
+BRG .rt_fdiv64_start R3 R5
+BRL .rt_fdiv64_align R3 R5
+BGE .rt_fdiv64_start R2 R4
+.rt_fdiv64_align
+BSL R3 R3 1
+BSR R8 R2 31
+OR R3 R3 R8
+BSL R2 R2 1
+LLOD R6 SP 0
+DEC R6 R6
+LSTR SP 0 R6
+.rt_fdiv64_start
+IMM R6 1
+IMM R7 0
+.rt_fdiv64_loop
+BSL R7 R7 1
+BSR R8 R6 31
+OR R7 R7 R8
+BSL R6 R6 1
+BRG .rt_fdiv64_sub R3 R5
+BRL .rt_fdiv64_next R3 R5
+BRL .rt_fdiv64_next R2 R4
+.rt_fdiv64_sub
+SETL R8 R2 R4
+SUB R2 R2 R4
+SUB R3 R3 R5
+ADD R3 R3 R8
+OR R6 R6 1
+.rt_fdiv64_next
+BSL R3 R3 1
+BSR R8 R2 31
+OR R3 R3 R8
+BSL R2 R2 1
+BSR R8 R7 24
+BRZ .rt_fdiv64_loop R8
+OR R2 R2 R3
+SETNE R2 R2 0
+AND R2 R2 1
+OR R2 R6 R2
+AND R3 R7 0xffffff
+POP R6
+POP R4
+CAL .rt_fpack64
+RET
+.rt_fdiv64_lhs
+LLOD R2 SP 3
+AND R3 R3 0x7fffffff
+OR R3 R3 R4
+RET
+.rt_fdiv64_by_zero
+BRZ .rt_fdiv64_nan R6
+IMM R2 0
+OR R3 R4 0x7ff00000
+RET
+.rt_fdiv64_nan
+IMM R2 0
+IMM R3 0x7ff80000
+RET
+.rt_fdiv64_zero
+IMM R2 0
+MOV R3 R4
+RET
+";
+
+// The magnitude becomes the mantissa as it is, `rt_fpack64` counts 55 of its bits as fractional, hence the exponent 1023 + 55
+const I64TOF64: &str = "\
+.rt_i64tof64
+LLOD R2 SP 1
+LLOD R3 SP 2
+AND R4 R3 0x80000000
+BRZ .rt_i64tof64_pack R4
+NOT R2 R2
+NOT R3 R3
+INC R2 R2
+BNZ .rt_i64tof64_pack R2
+INC R3 R3
+.rt_i64tof64_pack
+IMM R6 1078
+CAL .rt_fpack64
+RET
+";
+
+const U64TOF64: &str = "\
+.rt_u64tof64
+LLOD R2 SP 1
+LLOD R3 SP 2
+IMM R4 0
+IMM R6 1078
+CAL .rt_fpack64
+RET
+";
+
+// Truncates towards zero, values out of range become the minimum int64
+const F64TOI64: &str = "\
+.rt_f64toi64
+LLOD R2 SP 1
+LLOD R3 SP 2
+AND R4 R3 0x80000000
+BSR R5 R3 20
+AND R5 R5 0x7ff
+BRL .rt_f64toi64_zero R5 1023
+AND R3 R3 0xfffff
+OR R3 R3 0x100000
+SUB R5 R5 1075
+BRZ .rt_f64toi64_sign R5
+BRN .rt_f64toi64_right R5
+BRL .rt_f64toi64_left R5 11
+IMM R2 0
+IMM R3 0x80000000
+RET
+.rt_f64toi64_left
+BSL R3 R3 R5
+NEG R6 R5
+ADD R6 R6 32
+BSR R6 R2 R6
+OR R3 R3 R6
+BSL R2 R2 R5
+JMP .rt_f64toi64_sign
+.rt_f64toi64_right
+NEG R5 R5
+BRL .rt_f64toi64_bits R5 32
+SUB R5 R5 32
+BSR R2 R3 R5
+IMM R3 0
+JMP .rt_f64toi64_sign
+.rt_f64toi64_bits
+BSR R2 R2 R5
+NEG R6 R5
+ADD R6 R6 32
+BSL R6 R3 R6
+OR R2 R2 R6
+BSR R3 R3 R5
+.rt_f64toi64_sign
+BRZ .rt_f64toi64_end R4
+NOT R2 R2
+NOT R3 R3
+INC R2 R2
+BNZ .rt_f64toi64_end R2
+INC R3 R3
+.rt_f64toi64_end
+RET
+.rt_f64toi64_zero
+IMM R2 0
+IMM R3 0
+RET
+";
+
+// Float32 widens exactly, only the exponent bias changes
+const F32TOF64: &str = "\
+.rt_f32tof64
+LLOD R4 SP 1
+AND R3 R4 0x80000000
+BSR R5 R4 23
+AND R5 R5 0xff
+AND R4 R4 0x7fffff
+BRZ .rt_f32tof64_zero R5
+BNE .rt_f32tof64_finite R5 0xff
+IMM R5 0x7ff
+JMP .rt_f32tof64_pack
+.rt_f32tof64_finite
+ADD R5 R5 896
+.rt_f32tof64_pack
+BSL R5 R5 20
+OR R3 R3 R5
+BSR R5 R4 3
+OR R3 R3 R5
+BSL R2 R4 29
+RET
+.rt_f32tof64_zero
+IMM R2 0
+RET
+";
+
+// Keeps the top 23 bits of the mantissa and rounds the other 29 to nearest even
+const F64TOF32: &str = "\
+.rt_f64tof32
+LLOD R3 SP 1
+LLOD R4 SP 2
+AND R2 R4 0x80000000
+BSR R5 R4 20
+AND R5 R5 0x7ff
+BNE .rt_f64tof32_finite R5 0x7ff
+OR R2 R2 0x7f800000
+AND R4 R4 0xfffff
+OR R4 R4 R3
+BRZ .rt_f64tof32_end R4
+OR R2 R2 0x400000
+RET
+.rt_f64tof32_finite
+SUB R5 R5 896
+SBLE .rt_f64tof32_end R5 0
+SBGE .rt_f64tof32_inf R5 0xff
+AND R4 R4 0xfffff
+BSL R4 R4 3
+BSR R6 R3 29
+OR R4 R4 R6
+AND R3 R3 0x1fffffff
+BRL .rt_f64tof32_pack R3 0x10000000
+BRG .rt_f64tof32_up R3 0x10000000
+AND R6 R4 1
+BRZ .rt_f64tof32_pack R6
+.rt_f64tof32_up
+INC R4 R4
+BSR R6 R4 23
+BRZ .rt_f64tof32_pack R6
+IMM R4 0
+INC R5 R5
+BRE .rt_f64tof32_inf R5 0xff
+.rt_f64tof32_pack
+BSL R5 R5 23
+OR R2 R2 R5
+OR R2 R2 R4
+.rt_f64tof32_end
+RET
+.rt_f64tof32_inf
+OR R2 R2 0x7f800000
+RET
+";
+
+pub fn get_runtime_code(name: &str) -> &'static str {
+    match name {
+        "rt_mul64" => MUL64,
+        "rt_udivmod64" => UDIVMOD64,
+        "rt_sdivmod64" => SDIVMOD64,
+        "rt_shl64" => SHL64,
+        "rt_shr64" => SHR64,
+        "rt_sar64" => SAR64,
+        "rt_fpack64" => FPACK64,
+        "rt_fadd64" => FADD64,
+        "rt_fsub64" => FSUB64,
+        "rt_fmul64" => FMUL64,
+        "rt_fdiv64" => FDIV64,
+        "rt_i64tof64" => I64TOF64,
+        "rt_u64tof64" => U64TOF64,
+        "rt_f64toi64" => F64TOI64,
+        "rt_f32tof64" => F32TOF64,
+        "rt_f64tof32" => F64TOF32,
+        _ => unreachable!("Unknown runtime routine {}", name),
+    }
+}
+
+// Routines that call other routines
+pub fn get_runtime_deps(name: &str) -> &'static [&'static str] {
+    match name {
+        "rt_sdivmod64" => &["rt_udivmod64"],
+        "rt_fadd64" | "rt_fmul64" | "rt_fdiv64" | "rt_i64tof64" | "rt_u64tof64" => &["rt_fpack64"],
+        "rt_fsub64" => &["rt_fadd64"],
+        _ => &[],
+    }
+}
@@ -1,22 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// String literals of one module, each module numbers its labels separately as they all end up in one file
 #[derive(Debug, Clone)]
 pub struct StringsContainer {
+    module: u64,
     strings: Vec<String>,
 }
 
 impl StringsContainer {
     pub fn new() -> Self {
-        Self { strings: Vec::new() }
+        static MODULE_COUNT: AtomicU64 = AtomicU64::new(0);
+        Self {
+            module: MODULE_COUNT.fetch_add(1, Ordering::Relaxed),
+            strings: Vec::new(),
+        }
     }
 
-    pub fn register_string(&mut self, string: String) -> u64 {
-        if self.strings.contains(&string) {
-            return self.strings.iter().position(|string2| &string == string2).unwrap() as u64;
-        }
-        self.strings.push(string);
-        (self.strings.len() - 1) as u64
+    // Returns the label of the string's data
+    pub fn register_string(&mut self, string: String) -> String {
+        let idx = match self.strings.iter().position(|string2| &string == string2) {
+            Some(idx) => idx,
+            None => {
+                self.strings.push(string);
+                self.strings.len() - 1
+            }
+        };
+        self.get_label(idx)
+    }
+
+    fn get_label(&self, idx: usize) -> String {
+        format!(".str{}_{}", self.module, idx)
     }
 
-    pub fn get_strings(&self) -> Vec<String> {
-        self.strings.clone()
+    // format: (label, string)
+    pub fn get_strings(&self) -> Vec<(String, String)> {
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(idx, string)| (self.get_label(idx), string.clone()))
+            .collect()
     }
 }
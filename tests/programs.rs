@@ -0,0 +1,280 @@
+// Compiles the programs in `tests/programs/` and runs them on the emulator in `urcl/`.
+//
+// Every `name.hxgn` has a `name.expected` with what it prints, which has to be the same at every
+// optimization level. Programs that must not compile are checked for their error message instead.
+
+mod urcl;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const OPT_LEVELS: [u32; 3] = [0, 1, 2];
+
+fn programs_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("programs")
+}
+
+fn output_path(name: &str, opt_level: u32) -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}-O{}.urcl", name, opt_level))
+}
+
+fn run_compiler(name: &str, src: &Path, opt_level: u32) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hexagn"))
+        .arg(src)
+        .arg("-o")
+        .arg(output_path(name, opt_level))
+        .arg("-l")
+        .arg(programs_dir())
+        .arg("-O")
+        .arg(opt_level.to_string())
+        .output()
+        .unwrap()
+}
+
+// Runs the compiler on a source, returning the URCL or what it printed to stderr
+fn compile(name: &str, src: &Path, opt_level: u32) -> Result<String, String> {
+    let output = run_compiler(name, src, opt_level);
+    if output.status.success() {
+        Ok(fs::read_to_string(output_path(name, opt_level)).unwrap())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+fn run_program(name: &str) {
+    let src = programs_dir().join(format!("{}.hxgn", name));
+    let expected = fs::read_to_string(programs_dir().join(format!("{}.expected", name))).unwrap();
+    for opt_level in OPT_LEVELS {
+        let urcl = compile(name, &src, opt_level).unwrap_or_else(|err| panic!("{} does not compile at -O {}:\n{}", name, opt_level, err));
+        let output = urcl::run(&urcl).unwrap_or_else(|err| panic!("{} fails at -O {}: {}", name, opt_level, err));
+        assert_eq!(output, expected, "output of {} at -O {}", name, opt_level)
+    }
+}
+
+fn compile_snippet(name: &str, src: &str, opt_level: u32) -> Result<String, String> {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.hxgn", name));
+    fs::write(&path, src).unwrap();
+    compile(name, &path, opt_level)
+}
+
+// Compiles a snippet that has to be rejected with `message`
+fn compile_error(name: &str, src: &str, message: &str) {
+    for opt_level in OPT_LEVELS {
+        match compile_snippet(name, src, opt_level) {
+            Ok(_) => panic!("{} compiles at -O {}", name, opt_level),
+            Err(err) => assert!(err.contains(message), "{} at -O {} fails with:\n{}", name, opt_level, err),
+        }
+    }
+}
+
+// Compiles a snippet that has to be accepted, but with `message` as a warning
+fn compile_warning(name: &str, src: &str, message: &str) {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.hxgn", name));
+    fs::write(&path, src).unwrap();
+    for opt_level in OPT_LEVELS {
+        let output = run_compiler(name, &path, opt_level);
+        let err = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{} does not compile at -O {}:\n{}", name, opt_level, err);
+        assert!(err.contains(message), "{} at -O {} warns with:\n{}", name, opt_level, err)
+    }
+}
+
+macro_rules! programs {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[test]
+            fn $name() {
+                run_program(stringify!($name))
+            }
+        )*
+    };
+}
+
+programs! {
+    aliases,
+    arrays,
+    bitwise,
+    bools,
+    calls,
+    casts,
+    chars,
+    compound_assign,
+    constants,
+    enums,
+    float64,
+    floats,
+    for_loops,
+    globals,
+    if_else,
+    int64,
+    int_widths,
+    literal_width,
+    logic,
+    negation,
+    pointers,
+    scopes,
+    sizeof,
+    structs,
+    ternary,
+    unions,
+    while_loops,
+}
+
+#[test]
+fn return_value_mismatch() {
+    compile_error(
+        "return_without_value",
+        "int32 f() { return; }\nint8 main() { f(); return 0; }\n",
+        "Function f must return a value of type int32 at line 1",
+    );
+    compile_error(
+        "return_value_from_void",
+        "void f() { return 5; }\nint8 main() { f(); return 0; }\n",
+        "Function f returns void and cannot return a value at line 1",
+    )
+}
+
+#[test]
+fn index_is_an_integer() {
+    compile_error(
+        "index_is_an_integer",
+        "int8 main() { int32 xs[2]; float32 f = 1.0; xs[f] = 1; return 0; }\n",
+        "Index must be an integer, not float32",
+    )
+}
+
+#[test]
+fn if_condition_is_a_number() {
+    compile_error(
+        "if_condition_is_a_number",
+        "struct P { int32 x; }\nint8 main() { P p; if (p) { return 1; } return 0; }\n",
+        "Condition must be a bool or an integer, not P",
+    )
+}
+
+#[test]
+fn member_of_a_non_struct() {
+    compile_error(
+        "member_of_a_non_struct",
+        "int8 main() { int32 x = 1; x.y = 2; return 0; }\n",
+        "Type int32 is not a struct",
+    )
+}
+
+#[test]
+fn expression_errors_have_lines() {
+    compile_error(
+        "dereference_non_pointer",
+        "int8 main() {\n    int32 a = 1;\n    int32 b = *a;\n    return 0;\n}\n",
+        "Cannot dereference non-pointer type int32 at line 3",
+    );
+    compile_error(
+        "address_of_temporary",
+        "int8 main() {\n    int32 a = 1;\n\n    int32* p = &(a + 1);\n    return 0;\n}\n",
+        "Expression cannot be assigned to or have its address taken at line 4",
+    )
+}
+
+#[test]
+fn assign_to_const() {
+    compile_error(
+        "assign_to_const",
+        "int8 main() { const int32 a = 1; a = 2; return 0; }\n",
+        "Cannot assign to constant of type const int32",
+    )
+}
+
+#[test]
+fn pass_const_struct_as_mutable() {
+    compile_error(
+        "pass_const_struct_as_mutable",
+        "struct P { int32 x; }\nvoid f(P p) { p.x = 3; }\nvoid g(const P p) { }\n\
+         int8 main() { P q; q.x = 1; const P c = q; g(c); f(c); return 0; }\n",
+        "Passing const P as P discards const in call to f",
+    )
+}
+
+#[test]
+fn narrowing_argument_warning() {
+    compile_warning(
+        "narrowing_argument",
+        "void take8(int8 x) { }\nint8 main() {\n    int32 a = 300;\n    take8(a);\n    return 0;\n}\n",
+        "in call to take8 at line 4",
+    )
+}
+
+#[test]
+fn cast_struct_to_int() {
+    compile_error(
+        "cast_struct_to_int",
+        "struct P { int32 x; }\nint8 main() { P p; int32 a = p as int32; return 0; }\n",
+        "Cannot cast P to int32",
+    )
+}
+
+#[test]
+fn conversion_errors_have_lines() {
+    compile_error(
+        "compare_struct",
+        "struct P { int32 x; }\nint8 main() {\n    P p;\n    if (p == 1) { return 1; }\n    return 0;\n}\n",
+        "Cannot compare P with int at line 4",
+    );
+    compile_error(
+        "cast_int_to_struct",
+        "struct P { int32 x; }\nint8 main() {\n    int32 a = 1;\n    P p = a as P;\n    return 0;\n}\n",
+        "Cannot cast int32 to P at line 4",
+    )
+}
+
+#[test]
+fn variable_out_of_scope() {
+    compile_error(
+        "variable_out_of_scope",
+        "int8 main() { { int32 inner = 1; } inner = 2; return 0; }\n",
+        "Undefined variable inner",
+    )
+}
+
+#[test]
+fn duplicate_case() {
+    compile_error(
+        "duplicate_case",
+        "int8 main() { int32 x = 1; switch x { case 1: return 1; case 1: return 2; } return 0; }\n",
+        "Duplicate case",
+    )
+}
+
+#[test]
+fn unknown_enum_variant() {
+    compile_error(
+        "unknown_enum_variant",
+        "enum Color { Red, Green }\nint8 main() {\n    Color c = Color::Blue;\n    return 0;\n}\n",
+        "Enum Color has no variant Blue at line 3",
+    )
+}
+
+#[test]
+fn invalid_array_length() {
+    compile_error(
+        "array_length_not_constant",
+        "int8 main() {\n    int32 n = 2;\n    int32 xs[n];\n    return 0;\n}\n",
+        "Array length n is not a compile-time constant at line 3",
+    );
+    compile_error(
+        "array_length_negative",
+        "const int32 N = 1;\nint8 main() {\n    int32 xs[N - 2];\n    return 0;\n}\n",
+        "Array length -1 is negative at line 3",
+    )
+}
+
+#[test]
+fn float64_to_float32_warning() {
+    compile_warning(
+        "float64_to_float32",
+        "int8 main() {\n    float64 x = 1.5;\n    float32 y = x;\n    return 0;\n}\n",
+        "Implicit conversion from float64 to float32 may lose precision at line 3",
+    )
+}
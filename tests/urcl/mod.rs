@@ -0,0 +1,385 @@
+// A small URCL emulator, just enough to run the code the compiler emits and the inline URCL of the test programs.
+//
+// Instructions and data live in separate address spaces: labels before a `DW` are data addresses,
+// all other labels are instruction indices. The stack grows down from the top of memory.
+
+use std::collections::HashMap;
+
+const MEM_SIZE: usize = 1 << 16;
+const STEP_LIMIT: u64 = 50_000_000;
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(usize),
+    Sp,
+    Pc,
+    Imm(u32),
+    Label(String),
+    Port(String),
+}
+
+struct Instruction {
+    op: String,
+    operands: Vec<Operand>,
+    line: String,
+}
+
+// Runs a program and returns everything it printed
+pub fn run(src: &str) -> Result<String, String> {
+    let mut instructions = vec![];
+    let mut data: Vec<Operand> = vec![];
+    let mut labels = HashMap::new();
+    let mut pending = vec![];
+
+    for line in src.lines() {
+        let words = tokenize(line)?;
+        let Some(first) = words.first() else {
+            continue;
+        };
+        match first {
+            Word::Plain(word) if word.starts_with('.') => pending.push(word.clone()),
+            Word::Plain(word) if matches!(word.as_str(), "BITS" | "MINREG" | "MINHEAP" | "MINSTACK" | "RUN") => {}
+            Word::Plain(word) if word == "DW" => {
+                for label in pending.drain(..) {
+                    labels.insert(label, data.len() as u32);
+                }
+                for word in &words[1..] {
+                    match word {
+                        Word::Str(string) => data.extend(string.chars().map(|c| Operand::Imm(c as u32))),
+                        Word::Plain(word) if word == "[" || word == "]" => {}
+                        word => data.push(operand(word)?),
+                    }
+                }
+            }
+            Word::Plain(op) => {
+                for label in pending.drain(..) {
+                    labels.insert(label, instructions.len() as u32);
+                }
+                instructions.push(Instruction {
+                    op: op.clone(),
+                    operands: words[1..].iter().map(operand).collect::<Result<_, _>>()?,
+                    line: line.trim().to_string(),
+                });
+            }
+            Word::Str(_) => return Err(format!("unexpected string: {}", line)),
+        }
+    }
+    for label in pending {
+        labels.insert(label, instructions.len() as u32);
+    }
+
+    let mut mem = vec![0u32; MEM_SIZE];
+    for (addr, word) in data.iter().enumerate() {
+        mem[addr] = resolve(word, &labels)?;
+    }
+    Machine {
+        regs: [0; 32],
+        sp: MEM_SIZE as u32,
+        pc: 0,
+        mem,
+        out: String::new(),
+    }
+    .run(&instructions, &labels)
+}
+
+enum Word {
+    Plain(String),
+    Str(String),
+}
+
+fn unescape(c: char) -> Result<char, String> {
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        '0' => Ok('\0'),
+        '\\' | '"' | '\'' => Ok(c),
+        _ => Err(format!("unknown escape \\{}", c)),
+    }
+}
+
+// Splits a line into words, strings and character literals, dropping `//` comments
+fn tokenize(line: &str) -> Result<Vec<Word>, String> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut words = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            ' ' | '\t' | ',' => i += 1,
+            '/' if chars.get(i + 1) == Some(&'/') => break,
+            '"' => {
+                let mut string = String::new();
+                i += 1;
+                while chars.get(i) != Some(&'"') {
+                    let c = *chars.get(i).ok_or("unterminated string")?;
+                    if c == '\\' {
+                        i += 1;
+                        string.push(unescape(chars[i])?)
+                    } else {
+                        string.push(c)
+                    }
+                    i += 1
+                }
+                words.push(Word::Str(string));
+                i += 1
+            }
+            '\'' => {
+                let c = if chars[i + 1] == '\\' {
+                    i += 1;
+                    unescape(chars[i + 1])?
+                } else {
+                    chars[i + 1]
+                };
+                words.push(Word::Plain((c as u32).to_string()));
+                i += 3
+            }
+            '[' | ']' => {
+                words.push(Word::Plain(chars[i].to_string()));
+                i += 1
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !matches!(chars[i], ' ' | '\t' | ',' | '[' | ']') {
+                    i += 1
+                }
+                words.push(Word::Plain(chars[start..i].iter().collect()))
+            }
+        }
+    }
+    Ok(words)
+}
+
+fn operand(word: &Word) -> Result<Operand, String> {
+    let Word::Plain(word) = word else {
+        return Err(String::from("strings are only allowed in DW"));
+    };
+    let number = |digits: &str, radix: u32| {
+        i64::from_str_radix(digits, radix)
+            .map(|num| Operand::Imm(num as u32))
+            .map_err(|e| e.to_string())
+    };
+    match word.as_str() {
+        "SP" => Ok(Operand::Sp),
+        "PC" => Ok(Operand::Pc),
+        _ if word.starts_with('.') => Ok(Operand::Label(word.clone())),
+        _ if word.starts_with('%') => Ok(Operand::Port(word.clone())),
+        _ if word.starts_with('R') && word[1..].parse::<usize>().is_ok() => Ok(Operand::Reg(word[1..].parse().unwrap())),
+        _ if word.starts_with("0x") => number(&word[2..], 16),
+        _ if word.starts_with("-0x") => number(&word[3..], 16).map(|num| match num {
+            Operand::Imm(num) => Operand::Imm(num.wrapping_neg()),
+            _ => unreachable!(),
+        }),
+        _ if word.starts_with("0b") => number(&word[2..], 2),
+        _ => number(word, 10),
+    }
+}
+
+fn resolve(operand: &Operand, labels: &HashMap<String, u32>) -> Result<u32, String> {
+    match operand {
+        Operand::Imm(num) => Ok(*num),
+        Operand::Label(label) => labels.get(label).copied().ok_or(format!("unknown label {}", label)),
+        _ => Err(format!("{:?} is not a constant", operand)),
+    }
+}
+
+fn signed(num: u32) -> i64 {
+    num as i32 as i64
+}
+
+// Prints like C's `%g`: six significant digits without trailing zeros
+fn format_float(val: f32) -> String {
+    let rounded: f64 = format!("{:.5e}", val as f64).parse().unwrap();
+    format!("{}", rounded)
+}
+
+struct Machine {
+    regs: [u32; 32],
+    sp: u32,
+    pc: u32,
+    mem: Vec<u32>,
+    out: String,
+}
+
+impl Machine {
+    fn get(&self, operand: &Operand, labels: &HashMap<String, u32>) -> Result<u32, String> {
+        match operand {
+            Operand::Reg(reg) => Ok(self.regs[*reg]),
+            Operand::Sp => Ok(self.sp),
+            Operand::Pc => Ok(self.pc),
+            operand => resolve(operand, labels),
+        }
+    }
+
+    fn set(&mut self, operand: &Operand, val: u32) -> Result<(), String> {
+        match operand {
+            Operand::Reg(0) => {}
+            Operand::Reg(reg) => self.regs[*reg] = val,
+            Operand::Sp => self.sp = val,
+            _ => return Err(format!("cannot write to {:?}", operand)),
+        }
+        Ok(())
+    }
+
+    fn load(&self, addr: u32) -> Result<u32, String> {
+        self.mem.get(addr as usize).copied().ok_or(format!("load from {} is out of memory", addr))
+    }
+
+    fn store(&mut self, addr: u32, val: u32) -> Result<(), String> {
+        let word = self.mem.get_mut(addr as usize).ok_or(format!("store to {} is out of memory", addr))?;
+        *word = val;
+        Ok(())
+    }
+
+    fn run(mut self, instructions: &[Instruction], labels: &HashMap<String, u32>) -> Result<String, String> {
+        let mut steps = 0;
+        while (self.pc as usize) < instructions.len() {
+            steps += 1;
+            if steps > STEP_LIMIT {
+                return Err(format!("step limit reached, output so far:\n{}", self.out));
+            }
+            let ins = &instructions[self.pc as usize];
+            match self.step(ins, labels) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => return Err(format!("{} at `{}`, output so far:\n{}", err, ins.line, self.out)),
+            }
+        }
+        Ok(self.out)
+    }
+
+    // Executes one instruction, returns false on `HLT`
+    fn step(&mut self, ins: &Instruction, labels: &HashMap<String, u32>) -> Result<bool, String> {
+        let a = &ins.operands;
+        let arg = |idx: usize| -> Result<u32, String> { self.get(a.get(idx).ok_or(format!("missing operand {}", idx))?, labels) };
+        let float = |idx: usize| -> Result<f32, String> { arg(idx).map(f32::from_bits) };
+        let mut next = self.pc + 1;
+        let mut branch = |cond: bool, target: u32| {
+            if cond {
+                next = target
+            }
+        };
+
+        match ins.op.as_str() {
+            "HLT" => return Ok(false),
+            "NOP" => {}
+            "IMM" | "MOV" => self.set(&a[0], arg(1)?)?,
+            "ADD" => self.set(&a[0], arg(1)?.wrapping_add(arg(2)?))?,
+            "SUB" => self.set(&a[0], arg(1)?.wrapping_sub(arg(2)?))?,
+            "MLT" => self.set(&a[0], arg(1)?.wrapping_mul(arg(2)?))?,
+            "DIV" => self.set(&a[0], arg(1)?.checked_div(arg(2)?).ok_or("division by 0")?)?,
+            "MOD" => self.set(&a[0], arg(1)?.checked_rem(arg(2)?).ok_or("modulo by 0")?)?,
+            "SDIV" => {
+                let (lhs, rhs) = (arg(1)? as i32, arg(2)? as i32);
+                if rhs == 0 {
+                    return Err(String::from("division by 0"));
+                }
+                self.set(&a[0], lhs.wrapping_div(rhs) as u32)?
+            }
+            "AND" => self.set(&a[0], arg(1)? & arg(2)?)?,
+            "OR" => self.set(&a[0], arg(1)? | arg(2)?)?,
+            "XOR" => self.set(&a[0], arg(1)? ^ arg(2)?)?,
+            "NOR" => self.set(&a[0], !(arg(1)? | arg(2)?))?,
+            "NAND" => self.set(&a[0], !(arg(1)? & arg(2)?))?,
+            "XNOR" => self.set(&a[0], !(arg(1)? ^ arg(2)?))?,
+            "NOT" => self.set(&a[0], !arg(1)?)?,
+            "NEG" => self.set(&a[0], arg(1)?.wrapping_neg())?,
+            "INC" => self.set(&a[0], arg(1)?.wrapping_add(1))?,
+            "DEC" => self.set(&a[0], arg(1)?.wrapping_sub(1))?,
+            "LSH" => self.set(&a[0], arg(1)? << 1)?,
+            "RSH" => self.set(&a[0], arg(1)? >> 1)?,
+            "SRS" => self.set(&a[0], ((arg(1)? as i32) >> 1) as u32)?,
+            "BSL" => self.set(&a[0], arg(1)?.checked_shl(arg(2)?).unwrap_or(0))?,
+            "BSR" => self.set(&a[0], arg(1)?.checked_shr(arg(2)?).unwrap_or(0))?,
+            "BSS" => self.set(&a[0], ((arg(1)? as i32) >> arg(2)?.min(31)) as u32)?,
+
+            "PSH" => {
+                let val = arg(0)?;
+                self.sp = self.sp.wrapping_sub(1);
+                self.store(self.sp, val)?
+            }
+            "POP" => {
+                let val = self.load(self.sp)?;
+                self.sp += 1;
+                self.set(&a[0], val)?
+            }
+            "CAL" => {
+                let target = arg(0)?;
+                self.sp = self.sp.wrapping_sub(1);
+                self.store(self.sp, self.pc + 1)?;
+                next = target
+            }
+            "RET" => {
+                next = self.load(self.sp)?;
+                self.sp += 1
+            }
+            "LOD" => self.set(&a[0], self.load(arg(1)?)?)?,
+            "STR" => self.store(arg(0)?, arg(1)?)?,
+            "LLOD" => self.set(&a[0], self.load(arg(1)?.wrapping_add(arg(2)?))?)?,
+            "LSTR" => self.store(arg(0)?.wrapping_add(arg(1)?), arg(2)?)?,
+            "CPY" => self.store(arg(0)?, self.load(arg(1)?)?)?,
+
+            "JMP" => next = arg(0)?,
+            "BRE" => branch(arg(1)? == arg(2)?, arg(0)?),
+            "BNE" => branch(arg(1)? != arg(2)?, arg(0)?),
+            "BRL" => branch(arg(1)? < arg(2)?, arg(0)?),
+            "BRG" => branch(arg(1)? > arg(2)?, arg(0)?),
+            "BLE" => branch(arg(1)? <= arg(2)?, arg(0)?),
+            "BGE" => branch(arg(1)? >= arg(2)?, arg(0)?),
+            "SBRL" => branch(signed(arg(1)?) < signed(arg(2)?), arg(0)?),
+            "SBRG" => branch(signed(arg(1)?) > signed(arg(2)?), arg(0)?),
+            "SBLE" => branch(signed(arg(1)?) <= signed(arg(2)?), arg(0)?),
+            "SBGE" => branch(signed(arg(1)?) >= signed(arg(2)?), arg(0)?),
+            "BRZ" => branch(arg(1)? == 0, arg(0)?),
+            "BNZ" => branch(arg(1)? != 0, arg(0)?),
+            "BRN" => branch(signed(arg(1)?) < 0, arg(0)?),
+            "BRP" => branch(signed(arg(1)?) >= 0, arg(0)?),
+            "BRC" => branch(arg(1)?.checked_add(arg(2)?).is_none(), arg(0)?),
+            "BNC" => branch(arg(1)?.checked_add(arg(2)?).is_some(), arg(0)?),
+
+            "SETE" | "SETNE" | "SETL" | "SETG" | "SETLE" | "SETGE" | "SSETL" | "SSETG" | "SSETLE" | "SSETGE" | "SETC" | "SETNC" => {
+                let (lhs, rhs) = (arg(1)?, arg(2)?);
+                let (slhs, srhs) = (signed(lhs), signed(rhs));
+                let cond = match ins.op.as_str() {
+                    "SETE" => lhs == rhs,
+                    "SETNE" => lhs != rhs,
+                    "SETL" => lhs < rhs,
+                    "SETG" => lhs > rhs,
+                    "SETLE" => lhs <= rhs,
+                    "SETGE" => lhs >= rhs,
+                    "SSETL" => slhs < srhs,
+                    "SSETG" => slhs > srhs,
+                    "SSETLE" => slhs <= srhs,
+                    "SSETGE" => slhs >= srhs,
+                    "SETC" => lhs.checked_add(rhs).is_none(),
+                    _ => lhs.checked_add(rhs).is_some(),
+                };
+                self.set(&a[0], if cond { u32::MAX } else { 0 })?
+            }
+
+            "ITOF" => self.set(&a[0], (arg(1)? as i32 as f32).to_bits())?,
+            "FTOI" => self.set(&a[0], float(1)? as i32 as u32)?,
+            "FADD" => self.set(&a[0], (float(1)? + float(2)?).to_bits())?,
+            "FSUB" => self.set(&a[0], (float(1)? - float(2)?).to_bits())?,
+            "FMLT" => self.set(&a[0], (float(1)? * float(2)?).to_bits())?,
+            "FDIV" => self.set(&a[0], (float(1)? / float(2)?).to_bits())?,
+
+            "OUT" => {
+                let Operand::Port(port) = &a[0] else {
+                    return Err(String::from("expected a port"));
+                };
+                let val = arg(1)?;
+                match port.as_str() {
+                    "%NUMB" => self.out += &format!("{}\n", val),
+                    "%INT" => self.out += &format!("{}\n", signed(val)),
+                    "%FLOAT" => self.out += &format!("{}\n", format_float(f32::from_bits(val))),
+                    "%TEXT" => self.out.push(char::from_u32(val).ok_or("not a character")?),
+                    _ => return Err(format!("unknown port {}", port)),
+                }
+            }
+
+            op => return Err(format!("unknown instruction {}", op)),
+        }
+
+        self.pc = next;
+        Ok(true)
+    }
+}